@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE "builds" (
+	"id"	INTEGER,
+	"user"	INTEGER,
+	"challenge"	INTEGER NOT NULL,
+	"status"	TEXT NOT NULL,
+	"path"	TEXT,
+	"error"	TEXT,
+	"time"	TIMESTAMP NOT NULL,
+	PRIMARY KEY("id"),
+	FOREIGN KEY("user") REFERENCES "users"("id") ON DELETE CASCADE,
+	FOREIGN KEY("challenge") REFERENCES "challenges"("id") ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "artifacts" DROP COLUMN "pooled";
+ALTER TABLE "challenges" DROP COLUMN "pool";
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE "artifacts" ADD "pooled" BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE "challenges" ADD "pool" INTEGER;
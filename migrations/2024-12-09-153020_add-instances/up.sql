@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TABLE "instances" (
+	"id"	INTEGER,
+	"user"	INTEGER NOT NULL,
+	"challenge"	INTEGER NOT NULL,
+	"artifact"	INTEGER NOT NULL,
+	"info"	TEXT NOT NULL,
+	"start_at"	TIMESTAMP NOT NULL,
+	"stop_at"	TIMESTAMP,
+	PRIMARY KEY("id"),
+	UNIQUE("user","challenge","artifact"),
+	FOREIGN KEY("user") REFERENCES "users"("id") ON DELETE CASCADE,
+	FOREIGN KEY("challenge") REFERENCES "challenges"("id") ON DELETE CASCADE
+);
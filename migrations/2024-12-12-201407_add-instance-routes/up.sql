@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE "instances" ADD "routes" TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE "challenges" ADD "hardened" BOOLEAN NOT NULL DEFAULT 1;
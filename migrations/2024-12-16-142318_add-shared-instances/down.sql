@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+
+CREATE TABLE "new_instances" (
+	"id"	INTEGER,
+	"user"	INTEGER NOT NULL,
+	"challenge"	INTEGER NOT NULL,
+	"artifact"	INTEGER NOT NULL,
+	"info"	TEXT NOT NULL,
+	"start_at"	TIMESTAMP NOT NULL,
+	"stop_at"	TIMESTAMP,
+	"routes"	TEXT NOT NULL DEFAULT '{}',
+	PRIMARY KEY("id"),
+	UNIQUE("user","challenge","artifact"),
+	FOREIGN KEY("user") REFERENCES "users"("id") ON DELETE CASCADE,
+	FOREIGN KEY("challenge") REFERENCES "challenges"("id") ON DELETE CASCADE
+);
+
+INSERT INTO "new_instances" SELECT * FROM "instances" WHERE "user" IS NOT NULL;
+DROP TABLE "instances";
+ALTER TABLE "new_instances" RENAME TO "instances";
+
+ALTER TABLE "challenges" DROP COLUMN "shared";
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+ALTER TABLE "challenges" ADD "shared" BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE "new_instances" (
+	"id"	INTEGER,
+	"user"	INTEGER,
+	"challenge"	INTEGER NOT NULL,
+	"artifact"	INTEGER NOT NULL,
+	"info"	TEXT NOT NULL,
+	"start_at"	TIMESTAMP NOT NULL,
+	"stop_at"	TIMESTAMP,
+	"routes"	TEXT NOT NULL DEFAULT '{}',
+	PRIMARY KEY("id"),
+	UNIQUE("user","challenge","artifact"),
+	FOREIGN KEY("user") REFERENCES "users"("id") ON DELETE CASCADE,
+	FOREIGN KEY("challenge") REFERENCES "challenges"("id") ON DELETE CASCADE
+);
+
+INSERT INTO "new_instances" SELECT * FROM "instances";
+DROP TABLE "instances";
+ALTER TABLE "new_instances" RENAME TO "instances";
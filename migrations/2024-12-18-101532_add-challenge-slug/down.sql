@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX "challenges_slug";
+
+ALTER TABLE "challenges" DROP COLUMN "slug";
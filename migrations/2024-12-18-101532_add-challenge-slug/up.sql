@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE "challenges" ADD "slug" TEXT;
+
+CREATE UNIQUE INDEX "challenges_slug" ON "challenges" ("slug");
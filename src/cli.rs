@@ -0,0 +1,84 @@
+use std::process::ExitCode;
+
+use rocket::{Build, Rocket};
+
+use crate::{
+    db::Db,
+    functions::challenge::{sync_challenges, SyncAction},
+};
+
+const USAGE: &str = "usage: attackr [sync [--dry-run]]";
+
+pub async fn run(rocket: Rocket<Build>, args: &[String]) -> ExitCode {
+    match args.first().map(String::as_str) {
+        Some("sync") => match &args[1..] {
+            [] => sync(rocket, false).await,
+            [flag] if flag == "--dry-run" => sync(rocket, true).await,
+            _ => usage(),
+        },
+        _ => usage(),
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::from(2)
+}
+
+async fn sync(rocket: Rocket<Build>, dry_run: bool) -> ExitCode {
+    let rocket = match rocket.ignite().await {
+        Ok(rocket) => rocket,
+        Err(e) => {
+            eprintln!("failed to initialize: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let db = Db::get_one(&rocket).await.expect("database connection");
+
+    let report = match sync_challenges(&db, dry_run).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("failed to sync challenges: {e:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut failed = false;
+
+    for entry in &report.entries {
+        let action = match entry.action {
+            SyncAction::Create => "create",
+            SyncAction::Update => "update",
+            SyncAction::Unchanged => "unchanged",
+            SyncAction::Remove => "removed",
+            SyncAction::Error => "error",
+        };
+
+        let mut line = format!("{action:<10} {} ({})", entry.slug, entry.name);
+
+        if !entry.changes.is_empty() {
+            line += &format!(" [{}]", entry.changes.join(", "));
+        }
+
+        if entry.rebuild {
+            line += " +rebuild";
+        }
+
+        if let Some(error) = &entry.error {
+            failed = true;
+            line += &format!(": {error}");
+        }
+
+        println!("{line}");
+    }
+
+    if dry_run {
+        println!("dry run, nothing has been changed.");
+    }
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
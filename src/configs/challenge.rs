@@ -1,5 +1,5 @@
 use std::{
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     ops::RangeInclusive,
     path::PathBuf,
     sync::LazyLock,
@@ -9,7 +9,7 @@ use std::{
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
-use crate::core::conductor::DockerRunOptions;
+use crate::core::conductor::{BuildLimits, DockerRunOptions, Egress, SecurityProfile};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MappedAddr {
@@ -22,6 +22,10 @@ fn default_expiry() -> Option<Duration> {
     Some(Duration::from_secs(30 * 60))
 }
 
+fn default_max_states_size() -> u64 {
+    16 * 1024 * 1024
+}
+
 fn validate_docker_config(config: &DockerConfig) -> Result<(), ValidationError> {
     for addr in &config.mapped_addrs {
         if let Some(mapped_ports) = &addr.ports {
@@ -63,8 +67,26 @@ pub struct DockerConfig {
     #[serde(default = "default_expiry")]
     pub expiry: Option<Duration>,
     #[serde(default)]
+    pub extension: Option<Duration>,
+    #[serde(default)]
+    pub max_lifetime: Option<Duration>,
+    #[serde(default)]
+    pub cooldown: Option<Duration>,
+    #[serde(default)]
+    pub idle_timeout: Option<Duration>,
+    #[serde(default = "default_max_states_size")]
+    pub max_states_size: u64,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub max_per_user: Option<usize>,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub max_total: Option<usize>,
+    #[serde(default)]
     #[validate(nested)]
     pub options: DockerRunOptions,
+    #[serde(default)]
+    pub security: SecurityProfile,
 }
 
 impl Default for DockerConfig {
@@ -72,11 +94,71 @@ impl Default for DockerConfig {
         Self {
             mapped_addrs: default_mapped_addrs(),
             expiry: default_expiry(),
+            extension: Default::default(),
+            max_lifetime: Default::default(),
+            cooldown: Default::default(),
+            idle_timeout: Default::default(),
+            max_states_size: default_max_states_size(),
+            max_per_user: Default::default(),
+            max_total: Default::default(),
             options: Default::default(),
+            security: Default::default(),
+        }
+    }
+}
+
+fn default_workers() -> usize {
+    2
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BuildConfig {
+    #[serde(default = "default_workers")]
+    #[validate(range(min = 1))]
+    pub workers: usize,
+    #[serde(default)]
+    pub pool: usize,
+    #[serde(default)]
+    #[validate(nested)]
+    pub limits: BuildLimits,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            workers: default_workers(),
+            pool: Default::default(),
+            limits: Default::default(),
         }
     }
 }
 
+fn default_proxy_scheme() -> String {
+    "http".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ProxyConfig {
+    pub listen: SocketAddr,
+    #[validate(length(min = 1))]
+    pub domain: String,
+    #[serde(default = "default_proxy_scheme")]
+    pub scheme: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct GatewayConfig {
+    pub listen: SocketAddr,
+    #[validate(length(min = 1))]
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
 fn default_challenge_root() -> PathBuf {
     "challenges".into()
 }
@@ -85,16 +167,41 @@ fn default_artifact_root() -> PathBuf {
     "artifacts".into()
 }
 
+fn validate_config(config: &Config) -> Result<(), ValidationError> {
+    if config.docker.security.egress == Egress::Internal
+        && config.proxy.is_none()
+        && config.gateway.is_none()
+    {
+        return Err(ValidationError::new(
+            "'proxy' or 'gateway' is required for instances on internal network",
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_config"))]
 pub struct Config {
     #[serde(default = "default_challenge_root")]
     pub challenge_root: PathBuf,
     #[serde(default = "default_artifact_root")]
     pub artifact_root: PathBuf,
     #[serde(default)]
+    pub sync_root: Option<PathBuf>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub build: BuildConfig,
+    #[serde(default)]
     #[validate(nested)]
     pub docker: DockerConfig,
     #[serde(default)]
+    #[validate(nested)]
+    pub proxy: Option<ProxyConfig>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub gateway: Option<GatewayConfig>,
+    #[serde(default)]
     pub dynpoints: Option<PathBuf>,
     #[serde(default)]
     pub clear_on_solved: bool,
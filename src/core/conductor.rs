@@ -1,34 +1,33 @@
 use anyhow::{anyhow, bail, Result};
 use async_tempfile::{TempDir, TempFile};
-use bollard::{
-    container::{
-        Config as ContainerConfig, RemoveContainerOptions, StartContainerOptions,
-        WaitContainerOptions,
-    },
-    image::{BuildImageOptions, BuilderVersion, CreateImageOptions, RemoveImageOptions},
-    secret::{BuildInfoAux, HostConfig, PortBinding},
-    Docker,
-};
 use flate2::{write::GzEncoder, Compression};
-use futures_util::StreamExt;
+use futures_util::{stream::BoxStream, StreamExt};
+use itertools::Itertools;
 use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket},
     ops::RangeInclusive,
-    path::Path,
-    sync::LazyLock,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{LazyLock, Mutex},
+    time::Duration,
 };
 use tokio::{
     fs::{self, File},
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::RwLock,
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::{self, Instant},
 };
-use validator::Validate;
+use validator::{Validate, ValidationErrors};
 
 use crate::utils::fsext;
 
+use super::runtime::{self, ContainerSpec, ImageSpec, PortConflict, Runtime};
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct CmdStep {
     #[validate(length(min = 1))]
@@ -44,14 +43,146 @@ pub struct DockerStep {
     #[validate(length(min = 1))]
     pub path: String,
     #[serde(default)]
+    pub args: HashMap<String, String>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub config: DockerConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ComposeService {
+    #[validate(length(min = 1))]
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub args: HashMap<String, String>,
+    #[serde(default)]
+    pub envs: HashMap<String, String>,
+    #[serde(default)]
+    #[validate(nested)]
     pub config: DockerConfig,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ComposeStep {
+    #[validate(length(min = 1), nested)]
+    pub services: Vec<ComposeService>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildVars {
+    pub flag: String,
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub challenge_id: i32,
+    pub seed: String,
+}
+
+impl BuildVars {
+    fn entries(&self) -> [(&'static str, String); 5] {
+        [
+            ("flag", self.flag.clone()),
+            (
+                "user_id",
+                self.user_id.map(|x| x.to_string()).unwrap_or_default(),
+            ),
+            ("username", self.username.clone().unwrap_or_default()),
+            ("challenge_id", self.challenge_id.to_string()),
+            ("seed", self.seed.clone()),
+        ]
+    }
+
+    fn envs(&self) -> HashMap<String, String> {
+        self.entries()
+            .into_iter()
+            .map(|(key, value)| (format!("ATTACKR_{}", key.to_uppercase()), value))
+            .collect()
+    }
+
+    fn render(&self, template: &str) -> String {
+        let entries = self.entries();
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+
+            let key = rest[2..end].trim();
+
+            match entries.iter().find(|(name, _)| *name == key) {
+                Some((_, value)) => result.push_str(value),
+                None => result.push_str(&rest[..end + 2]),
+            }
+
+            rest = &rest[end + 2..];
+        }
+
+        result.push_str(rest);
+        result
+    }
+
+    fn render_map(&self, map: &HashMap<String, String>) -> HashMap<String, String> {
+        map.iter()
+            .map(|(key, value)| (key.clone(), self.render(value)))
+            .collect()
+    }
+
+    // whether the content refers to the per-user variables, either templated or as envs.
+    fn refers_user(content: &str) -> bool {
+        const USER_KEYS: [&str; 2] = ["user_id", "username"];
+
+        if USER_KEYS
+            .iter()
+            .any(|key| content.contains(&format!("ATTACKR_{}", key.to_uppercase())))
+        {
+            return true;
+        }
+
+        let mut rest = content;
+
+        while let Some(start) = rest.find("{{") {
+            rest = &rest[start + 2..];
+
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+
+            if USER_KEYS.contains(&rest[..end].trim()) {
+                return true;
+            }
+
+            rest = &rest[end + 2..];
+        }
+
+        false
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)]
 pub enum Step {
     Cmd(CmdStep),
     Docker(DockerStep),
+    Compose(ComposeStep),
+}
+
+impl Validate for Step {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            Step::Cmd(step) => step.validate(),
+            Step::Docker(step) => step.validate(),
+            Step::Compose(step) => step.validate(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
@@ -72,10 +203,103 @@ pub struct BinaryArtifact {
     pub path: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ReadinessCheck {
+    Tcp { port: String },
+    Http { port: String, path: String },
+    Exec { cmd: Vec<String> },
+}
+
+fn default_readiness_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_readiness_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadinessConfig {
+    #[serde(flatten)]
+    pub check: ReadinessCheck,
+    #[serde(default = "default_readiness_interval")]
+    pub interval: Duration,
+    #[serde(default = "default_readiness_timeout")]
+    pub timeout: Duration,
+}
+
+fn default_flag_env() -> String {
+    "ATTACKR_FLAG".to_string()
+}
+
+fn default_flag_file() -> String {
+    "flag".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FlagInjection {
+    Env {
+        #[serde(default = "default_flag_env")]
+        name: String,
+    },
+    File {
+        #[serde(default = "default_flag_file")]
+        path: String,
+    },
+}
+
+fn default_terminal_cmd() -> Vec<String> {
+    vec!["/bin/sh".to_string()]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TerminalConfig {
+    Tcp {
+        port: String,
+    },
+    Exec {
+        #[serde(default = "default_terminal_cmd")]
+        cmd: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, Default)]
+pub struct DockerResources {
+    #[validate(range(exclusive_min = 0.0))]
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub memory: Option<i64>,
+    #[serde(default)]
+    pub storage: Option<String>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub pids: Option<i64>,
+    #[serde(default)]
+    pub expiry: Option<Duration>,
+    #[serde(default)]
+    pub privileged: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, Default)]
 pub struct DockerConfig {
     #[serde(default)]
     pub exposed: Vec<String>,
+    #[serde(default)]
+    pub readiness: Option<ReadinessConfig>,
+    #[serde(default)]
+    pub proxied: Vec<String>,
+    #[serde(default)]
+    pub flag: Option<FlagInjection>,
+    #[serde(default)]
+    pub terminal: Option<TerminalConfig>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub resources: DockerResources,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,11 +308,27 @@ pub struct DockerArtifact {
     pub config: DockerConfig,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeServiceArtifact {
+    pub name: String,
+    pub id: String,
+    pub built: bool,
+    pub envs: HashMap<String, String>,
+    pub config: DockerConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeArtifact {
+    pub services: Vec<ComposeServiceArtifact>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)]
 pub enum Artifact {
     Binary(BinaryArtifact),
     Docker(DockerArtifact),
+    Compose(ComposeArtifact),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,6 +339,7 @@ pub struct BuildResult {
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct BuildInfo {
     #[serde(default)]
+    #[validate(nested)]
     pub steps: Vec<Step>,
     #[serde(default)]
     pub artifacts: Vec<ArtifactInfo>,
@@ -107,12 +348,25 @@ pub struct BuildInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunDockerResult {
     pub id: String,
+    pub containers: Vec<String>,
+    pub network: Option<String>,
     pub ports: HashMap<String, u16>,
     pub states: Box<Path>,
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    #[serde(default)]
+    pub upstreams: HashMap<String, SocketAddr>,
 }
 
-static STATES: LazyLock<RwLock<HashMap<String, TempDir>>> =
-    LazyLock::new(|| RwLock::new(HashMap::new()));
+const MANAGED_LABEL: &str = "attackr.managed";
+
+fn managed_labels() -> HashMap<String, String> {
+    HashMap::from([(MANAGED_LABEL.to_string(), "true".to_string())])
+}
+
+fn managed_filters() -> HashMap<String, Vec<String>> {
+    HashMap::from([("label".to_string(), vec![format!("{MANAGED_LABEL}=true")])])
+}
 
 fn default_addrs() -> Vec<IpAddr> {
     vec![
@@ -131,10 +385,144 @@ pub struct DockerRunOptions {
     pub memory: Option<i64>,
     #[serde(default)]
     pub storage: Option<String>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub pids: Option<i64>,
     #[serde(default = "default_addrs")]
     pub addrs: Vec<IpAddr>,
     #[serde(default)]
     pub ports: Option<RangeInclusive<u16>>,
+    #[serde(default = "default_states_root")]
+    pub states_root: PathBuf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub limits: DockerResourceLimits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, Default)]
+pub struct DockerResourceLimits {
+    #[validate(range(exclusive_min = 0.0))]
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub memory: Option<i64>,
+    #[serde(default)]
+    pub storage: Option<String>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub pids: Option<i64>,
+    #[serde(default)]
+    pub privileged: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Egress {
+    #[default]
+    Allow,
+    Internal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SecurityProfile {
+    #[serde(default)]
+    pub cap_drop: Vec<String>,
+    #[serde(default)]
+    pub cap_add: Vec<String>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub tmpfs: HashMap<String, String>,
+    #[serde(default)]
+    pub no_new_privileges: bool,
+    #[serde(default)]
+    pub seccomp: Option<PathBuf>,
+    #[serde(default)]
+    pub ulimits: Vec<Ulimit>,
+    #[serde(default)]
+    pub egress: Egress,
+}
+
+impl SecurityProfile {
+    async fn security_opt(&self) -> Result<Vec<String>> {
+        let mut opts = Vec::new();
+
+        if self.no_new_privileges {
+            opts.push("no-new-privileges".to_string());
+        }
+
+        if let Some(path) = &self.seccomp {
+            // the engine api expects the profile content rather than a path.
+            let profile = fs::read_to_string(path).await?;
+            opts.push(format!("seccomp={profile}"));
+        }
+
+        Ok(opts)
+    }
+}
+
+fn is_internal(security: Option<&SecurityProfile>) -> bool {
+    security.is_some_and(|security| security.egress == Egress::Internal)
+}
+
+fn default_states_root() -> PathBuf {
+    "states".into()
+}
+
+fn default_build_network() -> String {
+    "none".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BuildLimits {
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+    #[validate(range(exclusive_min = 0.0))]
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    pub memory: Option<i64>,
+    #[serde(default = "default_build_network")]
+    pub network: String,
+}
+
+impl Default for BuildLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Default::default(),
+            cpus: Default::default(),
+            memory: Default::default(),
+            network: default_build_network(),
+        }
+    }
+}
+
+struct StepContext<'a> {
+    vars: &'a BuildVars,
+    limits: &'a BuildLimits,
+    deadline: Option<Instant>,
+}
+
+impl StepContext<'_> {
+    async fn limit<F, T>(&self, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        match self.deadline {
+            Some(deadline) => time::timeout_at(deadline, future)
+                .await
+                .map_err(|_| anyhow!("build timed out."))?,
+            None => future.await,
+        }
+    }
 }
 
 impl Default for DockerRunOptions {
@@ -143,10 +531,41 @@ impl Default for DockerRunOptions {
             cpus: Default::default(),
             memory: Default::default(),
             storage: Default::default(),
+            pids: Default::default(),
             addrs: default_addrs(),
             ports: Default::default(),
+            states_root: default_states_root(),
+            limits: Default::default(),
+        }
+    }
+}
+
+fn scan_user_vars(path: &Path) -> Result<bool> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        let found = if file_type.is_dir() {
+            scan_user_vars(&entry.path())?
+        } else if file_type.is_file() {
+            BuildVars::refers_user(&String::from_utf8_lossy(&std::fs::read(entry.path())?))
+        } else {
+            false
+        };
+
+        if found {
+            return Ok(true);
         }
     }
+
+    Ok(false)
+}
+
+// artifacts built without a user, such as pooled ones, could not honor per-user variables.
+pub async fn uses_user_vars<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let path = path.as_ref().to_path_buf();
+
+    tokio::task::spawn_blocking(move || scan_user_vars(&path)).await?
 }
 
 pub async fn load_build_info<P: AsRef<Path>>(path: P) -> Result<BuildInfo> {
@@ -163,29 +582,47 @@ pub async fn load_build_info<P: AsRef<Path>>(path: P) -> Result<BuildInfo> {
     Ok(build)
 }
 
-async fn execute_cmd_step<P: AsRef<Path>>(path: P, flag: &str, step: &CmdStep) -> Result<()> {
-    let docker = Docker::connect_with_defaults()?;
+async fn write_log<W, B>(log: &mut W, content: B) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    B: AsRef<[u8]>,
+{
+    log.write_all(content.as_ref()).await?;
+    log.flush().await?;
 
-    let options = CreateImageOptions::<&str> {
-        from_image: &step.image,
-        ..Default::default()
-    };
+    Ok(())
+}
 
-    let mut stream = docker.create_image(Some(options), None, None);
-    let mut info = None;
+async fn pull_image<W>(runtime: &dyn Runtime, image: &str, log: &mut W) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    write_log(log, format!("pulling image {image}\n")).await?;
 
-    while let Some(Ok(inner)) = stream.next().await {
-        info = Some(inner)
-    }
+    runtime.pull_image(image).await
+}
 
-    let info = info.ok_or_else(|| anyhow!("no response from create_image"))?;
+async fn execute_cmd_step<P, W>(
+    path: P,
+    ctx: &StepContext<'_>,
+    step: &CmdStep,
+    log: &mut W,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
+    let runtime = runtime::runtime();
 
-    if let Some(err) = info.error {
-        bail!("pull image failed: {err}");
-    }
+    pull_image(runtime, &step.image, log).await?;
 
     let mut script = TempFile::new().await?;
-    let content = step.cmds.join("\n");
+    let content = step
+        .cmds
+        .iter()
+        .map(|cmd| ctx.vars.render(cmd))
+        .collect::<Vec<_>>()
+        .join("\n");
     script.write_all(content.as_bytes()).await?;
 
     let source_path = path
@@ -202,65 +639,75 @@ async fn execute_cmd_step<P: AsRef<Path>>(path: P, flag: &str, step: &CmdStep) -
     let dest_script_path = "/build.sh";
     let script_bind = format!("{script_path}:{dest_script_path}");
 
-    let host_config = HostConfig {
-        binds: Some(vec![source_bind, script_bind]),
-        ..Default::default()
-    };
+    let mut envs = ctx.vars.envs();
+    envs.extend(ctx.vars.render_map(&step.envs));
 
-    let env = format!("ATTACKR_FLAG={flag}");
+    let env: Vec<_> = envs
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
 
-    let entrypoint = vec!["/bin/sh", dest_script_path];
+    let entrypoint = vec!["/bin/sh".to_string(), dest_script_path.to_string()];
 
-    let config: ContainerConfig<&str> = ContainerConfig {
-        image: Some(&step.image),
-        host_config: Some(host_config),
+    let spec = ContainerSpec {
+        image: step.image.clone(),
         cmd: Some(entrypoint),
-        env: Some(vec![&env]),
-        working_dir: Some(working_dir),
+        env,
+        working_dir: Some(working_dir.to_string()),
+        labels: managed_labels(),
+        binds: vec![source_bind, script_bind],
+        network: Some(ctx.limits.network.clone()),
+        cpus: ctx.limits.cpus,
+        memory: ctx.limits.memory,
         ..Default::default()
     };
 
-    let created = docker.create_container::<&str, _>(None, config).await?;
+    let id = runtime.create_container(spec).await?;
 
-    let result = async {
-        docker
-            .start_container(&created.id, None::<StartContainerOptions<&str>>)
-            .await?;
+    let result = ctx
+        .limit(async {
+            runtime.start_container(&id).await?;
 
-        let mut stream = docker.wait_container(&created.id, None::<WaitContainerOptions<&str>>);
-        let mut resp = None;
+            let mut stream = runtime.logs(&id, true, "all");
 
-        while let Some(Ok(inner)) = stream.next().await {
-            resp = Some(inner);
-        }
+            while let Some(output) = stream.next().await {
+                write_log(log, output?).await?;
+            }
 
-        if let Some(err) = resp.and_then(|resp| resp.error) {
-            bail!("wait container error: {}", err.message.unwrap_or_default());
-        }
+            runtime.wait_container(&id).await?;
+
+            let info = runtime
+                .inspect_container(&id)
+                .await?
+                .ok_or_else(|| anyhow!("container {id} disappeared."))?;
 
-        let inspect = docker.inspect_container(&created.id, None).await?;
+            if let Some(exit_code) = info.exit_code {
+                write_log(log, format!("exited with code {exit_code}\n")).await?;
 
-        if let Some(state) = inspect.state {
-            if let Some(exit_code) = state.exit_code {
                 if exit_code != 0 {
                     bail!(
                         "exited with code {exit_code}: {}",
-                        state.error.unwrap_or_default()
+                        info.error.unwrap_or_default()
                     )
                 }
             }
-        }
 
-        Ok(())
+            Ok(())
+        })
+        .await;
+
+    // failing to log or clean up should not mask the error of the step itself.
+    if let Err(err) = &result {
+        _ = write_log(log, format!("{err}\n")).await;
     }
-    .await;
 
-    let options = RemoveContainerOptions {
-        force: true,
-        ..Default::default()
-    };
+    if let Err(err) = runtime.remove_container(&id).await {
+        if result.is_ok() {
+            return Err(err);
+        }
 
-    docker.remove_container(&created.id, Some(options)).await?;
+        log::warn!(target: "conductor", "failed to remove build container {id}: {err}");
+    }
 
     result
 }
@@ -300,15 +747,63 @@ where
     })
 }
 
-async fn execute_docker_step<P: AsRef<Path>>(
-    step: &DockerStep,
-    path: P,
-    flag: &str,
-) -> Result<DockerArtifact> {
-    let docker = Docker::connect_with_defaults()?;
+fn hash_chunk(hasher: &mut Sha256, chunk: &[u8]) {
+    hasher.update((chunk.len() as u64).to_be_bytes());
+    hasher.update(chunk);
+}
+
+// every entry is hashed as a type marker followed by length-prefixed path and content,
+// so that different trees never produce the same byte stream.
+pub(crate) fn hash_dir(hasher: &mut Sha256, root: &Path, relative: &Path) -> Result<()> {
+    let entries: Vec<_> = std::fs::read_dir(root.join(relative))?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.file_name(), entry.file_type()?))
+        })
+        .collect::<std::io::Result<_>>()?;
+
+    for (name, file_type) in entries.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        let relative = relative.join(name);
+        let path = root.join(&relative);
+
+        // links are hashed by their target and never followed.
+        let marker = if file_type.is_symlink() {
+            b'l'
+        } else if file_type.is_dir() {
+            b'd'
+        } else {
+            b'f'
+        };
+
+        hasher.update([marker]);
+        hash_chunk(hasher, relative.to_string_lossy().as_bytes());
+
+        match marker {
+            b'l' => hash_chunk(
+                hasher,
+                std::fs::read_link(path)?.to_string_lossy().as_bytes(),
+            ),
+            b'd' => hash_dir(hasher, root, &relative)?,
+            _ => hash_chunk(hasher, &std::fs::read(path)?),
+        }
+    }
 
+    Ok(())
+}
+
+async fn build_image<P, W>(
+    runtime: &dyn Runtime,
+    path: P,
+    args: &HashMap<String, String>,
+    shared: bool,
+    ctx: &StepContext<'_>,
+    log: &mut W,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
     let mut tarfile = Vec::new();
-    let path = path.as_ref().join(&step.path);
 
     {
         let enc = GzEncoder::new(&mut tarfile, Compression::default());
@@ -317,115 +812,339 @@ async fn execute_docker_step<P: AsRef<Path>>(
         tar.finish()?;
     }
 
-    let buildargs = HashMap::from([("ATTACKR_FLAG", flag)]);
+    // shared images get the flag at runtime, so per-user variables are left out
+    // and identical sources resolve to the same image.
+    let mut buildargs = match shared {
+        true => HashMap::new(),
+        false => ctx.vars.envs(),
+    };
+    buildargs.extend(ctx.vars.render_map(args));
 
-    let name = uuid::Uuid::new_v4().as_simple().to_string();
+    let name = match shared {
+        true => {
+            let mut hasher = Sha256::new();
+            hash_dir(&mut hasher, path.as_ref(), Path::new(""))?;
 
-    let options = BuildImageOptions {
-        buildargs,
-        t: &name,
-        dockerfile: "Dockerfile",
-        session: Some(name.clone()),
-        version: BuilderVersion::BuilderBuildKit,
-        ..Default::default()
-    };
+            for (key, value) in buildargs.iter().sorted() {
+                hash_chunk(&mut hasher, key.as_bytes());
+                hash_chunk(&mut hasher, value.as_bytes());
+            }
 
-    let mut stream = docker.build_image(options, None, Some(tarfile.into()));
+            format!("attackr-shared:{:x}", hasher.finalize())
+        }
+        false => uuid::Uuid::new_v4().as_simple().to_string(),
+    };
 
-    let mut result = None;
+    if shared {
+        if let Some(image) = runtime.inspect_image(&name).await? {
+            write_log(log, format!("reusing shared image {name}\n")).await?;
 
-    while let Some(Ok(bollard::models::BuildInfo { aux: Some(aux), .. })) = stream.next().await {
-        result = Some(aux);
+            return Ok(image.id);
+        }
     }
 
-    if let Some(result) = result {
-        match result {
-            BuildInfoAux::BuildKit(resp) => {
-                let error = resp
-                    .vertexes
-                    .into_iter()
-                    .last()
-                    .map(|vertex| vertex.error)
-                    .unwrap_or_default();
+    let spec = ImageSpec {
+        tag: name.clone(),
+        args: buildargs,
+        labels: managed_labels(),
+        cpus: ctx.limits.cpus,
+        memory: ctx.limits.memory,
+        network: ctx.limits.network.clone(),
+    };
+
+    write_log(log, format!("building image {name}\n")).await?;
+
+    let mut stream = runtime.build_image(spec, tarfile);
+
+    let mut id = None;
+    let mut error = None;
+
+    let result = ctx
+        .limit(async {
+            while let Some(event) = stream.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(err) => {
+                        _ = write_log(log, format!("{err}\n")).await;
+                        error = Some(err.to_string());
+                        break;
+                    }
+                };
+
+                if let Some(content) = &event.log {
+                    write_log(log, content).await?;
+                }
 
-                bail!("build image failed: {error}");
+                if let Some(err) = event.error {
+                    _ = write_log(log, format!("{err}\n")).await;
+                    error = Some(err);
+                }
+
+                if event.id.is_some() {
+                    id = event.id;
+                }
             }
-            BuildInfoAux::Default(inner) => {
-                let id = inner.id.ok_or_else(|| anyhow!("no image id got."))?;
-                return Ok(DockerArtifact {
-                    id,
-                    config: step.config.clone(),
-                });
+
+            Ok(())
+        })
+        .await;
+
+    if let Err(err) = result {
+        _ = write_log(log, format!("{err}\n")).await;
+        return Err(err);
+    }
+
+    if let Some(error) = error {
+        bail!("build image failed: {error}");
+    }
+
+    id.ok_or_else(|| anyhow!("no image id got."))
+}
+
+async fn execute_docker_step<P, W>(
+    step: &DockerStep,
+    path: P,
+    ctx: &StepContext<'_>,
+    log: &mut W,
+) -> Result<DockerArtifact>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
+    let runtime = runtime::runtime();
+
+    let path = path.as_ref().join(&step.path);
+    let shared = step.config.flag.is_some();
+    let id = build_image(runtime, &path, &step.args, shared, ctx, log).await?;
+
+    Ok(DockerArtifact {
+        id,
+        config: step.config.clone(),
+    })
+}
+
+async fn execute_compose_step<P, W>(
+    step: &ComposeStep,
+    path: P,
+    ctx: &StepContext<'_>,
+    log: &mut W,
+) -> Result<ComposeArtifact>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
+    let runtime = runtime::runtime();
+
+    let mut services: Vec<ComposeServiceArtifact> = Vec::new();
+
+    let result = async {
+        for service in &step.services {
+            if services.iter().any(|x| x.name == service.name) {
+                bail!("duplicated service '{}'.", service.name);
             }
+
+            write_log(log, format!("preparing service {}\n", service.name)).await?;
+
+            let (id, built) = match (&service.path, &service.image) {
+                (Some(source), None) => {
+                    let source = path.as_ref().join(source);
+                    (
+                        build_image(
+                            runtime,
+                            &source,
+                            &service.args,
+                            service.config.flag.is_some(),
+                            ctx,
+                            log,
+                        )
+                        .await?,
+                        true,
+                    )
+                }
+                (None, Some(image)) => {
+                    pull_image(runtime, image, log).await?;
+                    (image.clone(), false)
+                }
+                _ => bail!(
+                    "either 'path' or 'image' should be set for service '{}'.",
+                    service.name
+                ),
+            };
+
+            services.push(ComposeServiceArtifact {
+                name: service.name.clone(),
+                id,
+                built,
+                envs: ctx.vars.render_map(&service.envs),
+                config: service.config.clone(),
+            });
         }
+
+        Ok(())
+    }
+    .await;
+
+    let artifact = ComposeArtifact { services };
+
+    if let Err(err) = result {
+        remove_compose_artifact(&artifact).await;
+        return Err(err);
     }
 
-    bail!("no response from build_image.");
+    Ok(artifact)
 }
 
-fn is_port_free(addr: IpAddr, port: u16) -> bool {
+// host ports held by running instances, a port stays reserved until its instance is removed.
+static RESERVED_PORTS: LazyLock<Mutex<HashSet<u16>>> = LazyLock::new(Default::default);
+
+const PORT_ATTEMPTS: usize = 3;
+
+fn is_port_free(addr: IpAddr, port: u16, protocol: &str) -> bool {
     let addr = SocketAddr::new(addr, port);
-    TcpListener::bind(addr).is_ok()
+
+    match protocol {
+        "udp" => UdpSocket::bind(addr).is_ok(),
+        _ => TcpListener::bind(addr).is_ok(),
+    }
 }
 
-pub async fn run_docker(
-    artifact: &DockerArtifact,
+fn allocate_ports(
+    exposed: &[String],
     options: &DockerRunOptions,
-) -> Result<RunDockerResult> {
-    options.validate()?;
-    let states = TempDir::new().await?;
-
-    let mut ports: Vec<_> = options.ports.clone().unwrap_or(1024..=65535u16).collect();
+    excluded: &HashSet<u16>,
+) -> Result<HashMap<String, u16>> {
+    let mut candidates: Vec<_> = options.ports.clone().unwrap_or(1024..=65535u16).collect();
 
     {
         let mut rng = rand::rng();
-        ports.shuffle(&mut rng);
+        candidates.shuffle(&mut rng);
     }
     // rng is not 'Send' and not needed below, drop here.
 
-    let ports: HashMap<_, _> = artifact
-        .config
-        .exposed
-        .iter()
-        .cloned()
-        .zip(
-            ports
-                .into_iter()
-                .filter(|port| options.addrs.iter().all(|addr| is_port_free(*addr, *port))),
-        )
-        .collect();
+    let mut reserved = RESERVED_PORTS.lock().expect("lock poisoned.");
+
+    let mut candidates = candidates
+        .into_iter()
+        .filter(|port| !reserved.contains(port) && !excluded.contains(port));
+
+    let mut ports = HashMap::new();
+
+    for exposed in exposed {
+        let protocol = exposed.split_once('/').map(|(_, x)| x).unwrap_or("tcp");
 
-    if ports.len() < artifact.config.exposed.len() {
-        bail!("no enough ports available.");
+        let port = candidates
+            .find(|port| {
+                options
+                    .addrs
+                    .iter()
+                    .all(|addr| is_port_free(*addr, *port, protocol))
+            })
+            .ok_or_else(|| anyhow!("no enough ports available."))?;
+
+        ports.insert(exposed.clone(), port);
     }
 
-    let docker = Docker::connect_with_defaults()?;
+    reserved.extend(ports.values().copied());
 
-    let port_bindings = ports
-        .clone()
-        .into_iter()
+    Ok(ports)
+}
+
+pub fn reserve_ports(instance: &RunDockerResult) {
+    let mut reserved = RESERVED_PORTS.lock().expect("lock poisoned.");
+    reserved.extend(instance.ports.values().copied());
+}
+
+fn release_ports(ports: &HashMap<String, u16>) {
+    let mut reserved = RESERVED_PORTS.lock().expect("lock poisoned.");
+
+    for port in ports.values() {
+        reserved.remove(port);
+    }
+}
+
+fn is_port_conflict(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<PortConflict>().is_some()
+}
+
+fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+
+    let (value, unit) = size.split_at(split);
+    let value: f64 = value.parse()?;
+
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit.trim_end_matches('b').trim_end_matches('i');
+
+    let scale: u64 = match unit {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => bail!("invalid size '{size}'."),
+    };
+
+    Ok((value * scale as f64) as u64)
+}
+
+// non-positive values mean unlimited to docker, so they never pass the limit.
+fn bounded<T: PartialOrd + Default>(value: Option<T>, limit: Option<T>) -> Option<T> {
+    match (value, limit) {
+        (Some(value), limit) if value <= T::default() => limit,
+        (Some(value), Some(limit)) if value > limit => Some(limit),
+        (None, limit) => limit,
+        (value, _) => value,
+    }
+}
+
+fn bounded_storage(value: Option<&String>, limit: Option<&String>) -> Result<Option<String>> {
+    let (Some(value), Some(limit)) = (value, limit) else {
+        return Ok(value.or(limit).cloned());
+    };
+
+    match parse_size(value)? > parse_size(limit)? {
+        true => Ok(Some(limit.clone())),
+        false => Ok(Some(value.clone())),
+    }
+}
+
+async fn create_container_spec(
+    ports: &HashMap<String, u16>,
+    states: &Path,
+    resources: &DockerResources,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+) -> Result<ContainerSpec> {
+    let limits = &options.limits;
+
+    if resources.privileged && !limits.privileged {
+        bail!("privileged containers are not allowed.");
+    }
+
+    let cpus = bounded(resources.cpus.or(options.cpus), limits.cpus);
+    let memory = bounded(resources.memory.or(options.memory), limits.memory);
+    let pids = bounded(resources.pids.or(options.pids), limits.pids);
+    let storage = bounded_storage(
+        resources.storage.as_ref().or(options.storage.as_ref()),
+        limits.storage.as_ref(),
+    )?;
+
+    let ports = ports
+        .iter()
         .map(|(exposed, port)| {
-            (
-                exposed,
-                Some(
-                    options
-                        .addrs
-                        .iter()
-                        .map(|addr| PortBinding {
-                            host_ip: Some(addr.to_string()),
-                            host_port: Some(port.to_string()),
-                        })
-                        .collect(),
-                ),
-            )
+            let addrs = options
+                .addrs
+                .iter()
+                .map(|addr| SocketAddr::new(*addr, *port))
+                .collect();
+
+            (exposed.clone(), addrs)
         })
         .collect();
 
-    let storage_opt = options
-        .storage
-        .clone()
-        .map(|size| HashMap::from([("size".to_string(), size)]));
-
     let binds = vec![format!(
         "{}:/var/lib/attackr",
         states
@@ -433,92 +1152,914 @@ pub async fn run_docker(
             .ok_or_else(|| anyhow!("inconvertible path."))?
     )];
 
-    let host_config = HostConfig {
-        port_bindings: Some(port_bindings),
-        publish_all_ports: Some(false),
-        cpu_quota: options.cpus.map(|x| (x * 100000.0).round() as i64),
-        memory: options.memory,
-        binds: Some(binds),
-        storage_opt,
+    let mut spec = ContainerSpec {
+        labels: managed_labels(),
+        binds,
+        ports,
+        cpus,
+        memory,
+        pids,
+        storage,
+        privileged: resources.privileged,
         ..Default::default()
     };
 
-    let config = ContainerConfig {
-        image: Some(artifact.id.clone()),
-        host_config: Some(host_config),
-        ..Default::default()
-    };
+    if let Some(security) = security {
+        spec.cap_drop = security.cap_drop.clone();
+        spec.cap_add = security.cap_add.clone();
+        spec.read_only = security.read_only;
+        spec.tmpfs = security.tmpfs.clone();
+        spec.security_opt = security.security_opt().await?;
+        spec.ulimits = security
+            .ulimits
+            .iter()
+            .map(|ulimit| runtime::Ulimit {
+                name: ulimit.name.clone(),
+                soft: ulimit.soft,
+                hard: ulimit.hard,
+            })
+            .collect();
+    }
 
-    let created = docker.create_container::<&str, _>(None, config).await?;
+    Ok(spec)
+}
+
+async fn create_network(runtime: &dyn Runtime, internal: bool) -> Result<String> {
+    let network = uuid::Uuid::new_v4().as_simple().to_string();
 
-    docker
-        .start_container(&created.id, None::<StartContainerOptions<&str>>)
+    runtime
+        .create_network(&network, internal, managed_labels())
         .await?;
 
-    let path = states.dir_path().clone().into_boxed_path();
+    Ok(network)
+}
+
+fn local_upstreams(
+    ports: &HashMap<String, u16>,
+    options: &DockerRunOptions,
+) -> Result<HashMap<String, SocketAddr>> {
+    ports
+        .iter()
+        .map(|(exposed, port)| Ok((exposed.clone(), local_addr(options, *port)?)))
+        .collect()
+}
+
+// ports are not published on internal networks, reach the container directly instead.
+async fn container_upstreams(
+    runtime: &dyn Runtime,
+    container: &str,
+    network: &str,
+    exposed: &[String],
+) -> Result<HashMap<String, SocketAddr>> {
+    let info = runtime
+        .inspect_container(container)
+        .await?
+        .ok_or_else(|| anyhow!("container {container} disappeared."))?;
+
+    let addr = *info
+        .addrs
+        .get(network)
+        .ok_or_else(|| anyhow!("container has no address on network '{network}'."))?;
+
+    exposed
+        .iter()
+        .map(|exposed| {
+            let port = exposed.split('/').next().unwrap_or_default().parse()?;
+            Ok((exposed.clone(), SocketAddr::new(addr, port)))
+        })
+        .collect()
+}
 
-    STATES.write().await.insert(created.id.clone(), states);
+pub fn local_addr(options: &DockerRunOptions, port: u16) -> Result<SocketAddr> {
+    let addr = options
+        .addrs
+        .first()
+        .ok_or_else(|| anyhow!("no address available."))?;
 
-    Ok(RunDockerResult {
-        id: created.id,
-        states: path,
-        ports,
-    })
+    let addr = match addr {
+        IpAddr::V4(addr) if addr.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(addr) if addr.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        addr => *addr,
+    };
+
+    Ok(SocketAddr::new(addr, port))
 }
 
-pub async fn stop_docker(id: &str) -> Result<()> {
-    STATES.write().await.remove(id);
+async fn probe_http(addr: SocketAddr, path: &str) -> Result<bool> {
+    let mut stream = TcpStream::connect(addr).await?;
 
-    let docker = Docker::connect_with_defaults()?;
+    let request = format!("GET {path} HTTP/1.0\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
 
-    let options = RemoveContainerOptions {
-        force: true,
-        ..Default::default()
+    let mut buf = [0; 16];
+    let len = stream.read(&mut buf).await?;
+
+    // status line looks like 'HTTP/1.1 200 OK'.
+    let status = String::from_utf8_lossy(&buf[..len]);
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+
+    Ok(code.starts_with('2') || code.starts_with('3'))
+}
+
+async fn probe_exec(runtime: &dyn Runtime, container: &str, cmd: &[String]) -> Result<bool> {
+    Ok(runtime.exec(container, cmd).await? == Some(0))
+}
+
+async fn probe(
+    runtime: &dyn Runtime,
+    container: &str,
+    check: &ReadinessCheck,
+    upstreams: &HashMap<String, SocketAddr>,
+) -> Result<bool> {
+    let upstream = |exposed: &String| {
+        upstreams
+            .get(exposed)
+            .copied()
+            .ok_or_else(|| anyhow!("port '{exposed}' is not exposed."))
     };
 
-    docker.remove_container(id, Some(options)).await?;
+    match check {
+        ReadinessCheck::Tcp { port: exposed } => {
+            let addr = upstream(exposed)?;
+            Ok(TcpStream::connect(addr).await.is_ok())
+        }
+        ReadinessCheck::Http {
+            port: exposed,
+            path,
+        } => {
+            let addr = upstream(exposed)?;
+            Ok(probe_http(addr, path).await.unwrap_or(false))
+        }
+        ReadinessCheck::Exec { cmd } => probe_exec(runtime, container, cmd).await,
+    }
+}
+
+async fn wait_ready(
+    runtime: &dyn Runtime,
+    container: &str,
+    readiness: &ReadinessConfig,
+    upstreams: &HashMap<String, SocketAddr>,
+) -> Result<()> {
+    let deadline = Instant::now() + readiness.timeout;
+
+    loop {
+        let attempt = probe(runtime, container, &readiness.check, upstreams);
+
+        if let Ok(ready) = time::timeout(readiness.interval, attempt).await {
+            if ready? {
+                return Ok(());
+            }
+        }
+
+        if Instant::now() >= deadline {
+            bail!("instance did not become ready in time.");
+        }
+
+        time::sleep(readiness.interval).await;
+    }
+}
+
+async fn capture_logs(runtime: &dyn Runtime, instance: &RunDockerResult, tail: &str) -> String {
+    let mut logs = String::new();
+
+    for id in &instance.containers {
+        logs.push_str(&format!("==> {id}\n"));
+
+        let mut stream = runtime.logs(id, false, tail);
+
+        while let Some(Ok(output)) = stream.next().await {
+            logs.push_str(&String::from_utf8_lossy(&output));
+        }
+    }
+
+    logs
+}
+
+async fn inject_flag(
+    injection: &FlagInjection,
+    flag: &str,
+    states: &Path,
+    env: &mut Vec<String>,
+    secrets: &mut Vec<String>,
+) -> Result<()> {
+    match injection {
+        FlagInjection::Env { name } => env.push(format!("{name}={flag}")),
+        FlagInjection::File { path } => {
+            let relative = Path::new(path);
+
+            if relative
+                .components()
+                .any(|x| !matches!(x, std::path::Component::Normal(_)))
+            {
+                bail!("invalid flag file path '{path}'.");
+            }
+
+            let target = states.join(relative);
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            fs::write(target, format!("{flag}\n")).await?;
+
+            if !secrets.contains(path) {
+                secrets.push(path.clone());
+            }
+        }
+    }
 
     Ok(())
 }
 
-async fn remove_docker_artifact(artifact: &DockerArtifact) -> Result<()> {
-    let docker = Docker::connect_with_defaults()?;
+pub async fn run_docker(
+    artifact: &DockerArtifact,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+) -> Result<RunDockerResult> {
+    options.validate()?;
 
-    let options = RemoveImageOptions {
-        force: true,
-        ..Default::default()
+    let mut excluded = HashSet::new();
+
+    for _ in 1..PORT_ATTEMPTS {
+        match launch_docker(artifact, options, security, flag, None, &mut excluded).await {
+            Err(err) if is_port_conflict(&err) => {
+                log::warn!(target: "conductor", "port conflict on launching docker instance, retrying: {err}")
+            }
+            result => return result,
+        }
+    }
+
+    launch_docker(artifact, options, security, flag, None, &mut excluded).await
+}
+
+pub async fn reset_docker(
+    artifact: &DockerArtifact,
+    instance: &RunDockerResult,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+) -> Result<RunDockerResult> {
+    options.validate()?;
+
+    // ports stay reserved so that the new containers could take them over.
+    if let Err(err) = remove_resources(runtime::runtime(), instance).await {
+        log::warn!(target: "conductor", "failed to remove docker instance on reset: {err}");
+    }
+
+    let ports = Some(&instance.ports);
+
+    launch_docker(
+        artifact,
+        options,
+        security,
+        flag,
+        ports,
+        &mut HashSet::new(),
+    )
+    .await
+}
+
+async fn launch_docker(
+    artifact: &DockerArtifact,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+    ports: Option<&HashMap<String, u16>>,
+    excluded: &mut HashSet<u16>,
+) -> Result<RunDockerResult> {
+    let runtime = runtime::runtime();
+    let states = create_states(options).await?;
+    let internal = is_internal(security);
+
+    let ports = match (internal, ports) {
+        (true, _) => Ok(HashMap::new()),
+        (false, Some(ports)) => Ok(ports.clone()),
+        (false, None) => allocate_ports(&artifact.config.exposed, options, excluded),
     };
 
-    docker
-        .remove_image(&artifact.id, Some(options), None)
+    let ports = match ports {
+        Ok(ports) => ports,
+        Err(err) => {
+            _ = fs::remove_dir_all(&states).await;
+            return Err(err);
+        }
+    };
+
+    // avoid picking the same ports again if docker fails to bind them.
+    excluded.extend(ports.values().copied());
+
+    let network = match internal {
+        true => match create_network(runtime, true).await {
+            Ok(network) => Some(network),
+            Err(err) => {
+                release_ports(&ports);
+                _ = fs::remove_dir_all(&states).await;
+                return Err(err);
+            }
+        },
+        false => None,
+    };
+
+    let mut instance = RunDockerResult {
+        id: String::new(),
+        containers: Vec::new(),
+        network,
+        states: states.into_boxed_path(),
+        ports,
+        secrets: Vec::new(),
+        upstreams: HashMap::new(),
+    };
+
+    let result = async {
+        let mut spec = create_container_spec(
+            &instance.ports,
+            &instance.states,
+            &artifact.config.resources,
+            options,
+            security,
+        )
         .await?;
+        spec.image = artifact.id.clone();
+        spec.network = instance.network.clone();
+
+        if let Some(injection) = &artifact.config.flag {
+            inject_flag(
+                injection,
+                flag,
+                &instance.states,
+                &mut spec.env,
+                &mut instance.secrets,
+            )
+            .await?;
+        }
+
+        let id = runtime.create_container(spec).await?;
+
+        instance.id = id.clone();
+        instance.containers.push(id.clone());
+
+        runtime.start_container(&id).await?;
+
+        instance.upstreams = match &instance.network {
+            Some(network) => {
+                container_upstreams(runtime, &id, network, &artifact.config.exposed).await?
+            }
+            None => local_upstreams(&instance.ports, options)?,
+        };
+
+        if let Some(readiness) = &artifact.config.readiness {
+            wait_ready(runtime, &id, readiness, &instance.upstreams).await?;
+        }
+
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        let logs = capture_logs(runtime, &instance, "all").await;
+        log::warn!(target: "conductor", "failed to launch docker instance: {err}\n{logs}");
+
+        _ = remove_instance(runtime, &instance).await;
+        return Err(err);
+    }
+
+    Ok(instance)
+}
+
+pub async fn run_compose(
+    artifact: &ComposeArtifact,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+) -> Result<RunDockerResult> {
+    options.validate()?;
+
+    let mut excluded = HashSet::new();
+
+    for _ in 1..PORT_ATTEMPTS {
+        match launch_compose(artifact, options, security, flag, None, &mut excluded).await {
+            Err(err) if is_port_conflict(&err) => {
+                log::warn!(target: "conductor", "port conflict on launching compose instance, retrying: {err}")
+            }
+            result => return result,
+        }
+    }
+
+    launch_compose(artifact, options, security, flag, None, &mut excluded).await
+}
+
+pub async fn reset_compose(
+    artifact: &ComposeArtifact,
+    instance: &RunDockerResult,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+) -> Result<RunDockerResult> {
+    options.validate()?;
+
+    // ports stay reserved so that the new containers could take them over.
+    if let Err(err) = remove_resources(runtime::runtime(), instance).await {
+        log::warn!(target: "conductor", "failed to remove compose instance on reset: {err}");
+    }
+
+    let ports = Some(&instance.ports);
+
+    launch_compose(
+        artifact,
+        options,
+        security,
+        flag,
+        ports,
+        &mut HashSet::new(),
+    )
+    .await
+}
+
+async fn launch_compose(
+    artifact: &ComposeArtifact,
+    options: &DockerRunOptions,
+    security: Option<&SecurityProfile>,
+    flag: &str,
+    ports: Option<&HashMap<String, u16>>,
+    excluded: &mut HashSet<u16>,
+) -> Result<RunDockerResult> {
+    let runtime = runtime::runtime();
+    let states = create_states(options).await?;
+    let internal = is_internal(security);
+
+    let exposed: Vec<_> = artifact
+        .services
+        .iter()
+        .flat_map(|service| {
+            service
+                .config
+                .exposed
+                .iter()
+                .map(|exposed| format!("{}:{exposed}", service.name))
+        })
+        .collect();
+
+    let ports = match (internal, ports) {
+        (true, _) => Ok(HashMap::new()),
+        (false, Some(ports)) => Ok(ports.clone()),
+        (false, None) => allocate_ports(&exposed, options, excluded),
+    };
+
+    let ports = match ports {
+        Ok(ports) => ports,
+        Err(err) => {
+            _ = fs::remove_dir_all(&states).await;
+            return Err(err);
+        }
+    };
+
+    // avoid picking the same ports again if docker fails to bind them.
+    excluded.extend(ports.values().copied());
+
+    let network = match create_network(runtime, internal).await {
+        Ok(network) => network,
+        Err(err) => {
+            release_ports(&ports);
+            _ = fs::remove_dir_all(&states).await;
+            return Err(err);
+        }
+    };
+
+    let mut containers = Vec::new();
+    let mut secrets = Vec::new();
+    let mut upstreams = HashMap::new();
+
+    let result = async {
+        let mut service_upstreams = Vec::new();
+
+        for service in &artifact.services {
+            let service_ports = service
+                .config
+                .exposed
+                .iter()
+                .filter_map(|exposed| {
+                    let port = ports.get(&format!("{}:{exposed}", service.name))?;
+                    Some((exposed.clone(), *port))
+                })
+                .collect();
+
+            let mut spec = create_container_spec(
+                &service_ports,
+                &states,
+                &service.config.resources,
+                options,
+                security,
+            )
+            .await?;
+            spec.image = service.id.clone();
+            spec.exposed = service.config.exposed.clone();
+            spec.network = Some(network.clone());
+            spec.aliases = vec![service.name.clone()];
+            spec.env = service
+                .envs
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+
+            if let Some(injection) = &service.config.flag {
+                inject_flag(injection, flag, &states, &mut spec.env, &mut secrets).await?;
+            }
+
+            let id = runtime.create_container(spec).await?;
+            containers.push(id.clone());
+
+            runtime.start_container(&id).await?;
+
+            let service_upstream = match internal {
+                true => {
+                    container_upstreams(runtime, &id, &network, &service.config.exposed).await?
+                }
+                false => local_upstreams(&service_ports, options)?,
+            };
+
+            upstreams.extend(
+                service_upstream
+                    .iter()
+                    .map(|(exposed, addr)| (format!("{}:{exposed}", service.name), *addr)),
+            );
+
+            service_upstreams.push(service_upstream);
+        }
+
+        for ((service, container), upstreams) in artifact
+            .services
+            .iter()
+            .zip(&containers)
+            .zip(&service_upstreams)
+        {
+            if let Some(readiness) = &service.config.readiness {
+                wait_ready(runtime, container, readiness, upstreams).await?;
+            }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    let instance = RunDockerResult {
+        id: network.clone(),
+        containers,
+        network: Some(network),
+        states: states.into_boxed_path(),
+        ports,
+        secrets,
+        upstreams,
+    };
+
+    if let Err(err) = result {
+        let logs = capture_logs(runtime, &instance, "all").await;
+        log::warn!(target: "conductor", "failed to launch compose instance: {err}\n{logs}");
+
+        _ = remove_instance(runtime, &instance).await;
+        return Err(err);
+    }
+
+    Ok(instance)
+}
+
+async fn create_states(options: &DockerRunOptions) -> Result<PathBuf> {
+    let path = options
+        .states_root
+        .join(uuid::Uuid::new_v4().as_simple().to_string());
+
+    fs::create_dir_all(&path).await?;
+
+    // bind mounts require an absolute path.
+    Ok(fs::canonicalize(path).await?)
+}
+
+async fn remove_resources(runtime: &dyn Runtime, instance: &RunDockerResult) -> Result<()> {
+    let mut result = Ok(());
+
+    for id in &instance.containers {
+        if let Err(err) = runtime.remove_container(id).await {
+            result = Err(err);
+        }
+    }
+
+    if let Some(network) = &instance.network {
+        if let Err(err) = runtime.remove_network(network).await {
+            result = Err(err);
+        }
+    }
+
+    if instance.states.exists() {
+        fs::remove_dir_all(&instance.states).await?;
+    }
+
+    result
+}
+
+async fn remove_instance(runtime: &dyn Runtime, instance: &RunDockerResult) -> Result<()> {
+    let result = remove_resources(runtime, instance).await;
+
+    release_ports(&instance.ports);
+
+    result
+}
+
+pub async fn stop_docker(instance: &RunDockerResult) -> Result<()> {
+    remove_instance(runtime::runtime(), instance).await
+}
+
+pub async fn is_docker_alive(instance: &RunDockerResult) -> Result<bool> {
+    let runtime = runtime::runtime();
+
+    for id in &instance.containers {
+        let running = runtime
+            .inspect_container(id)
+            .await?
+            .is_some_and(|info| info.running);
+
+        if !running {
+            return Ok(false);
+        }
+    }
+
+    Ok(!instance.containers.is_empty())
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DockerStats {
+    pub cpu: f64,
+    pub memory: u64,
+    pub memory_limit: u64,
+    pub network: u64,
+}
+
+pub async fn get_docker_stats(instance: &RunDockerResult) -> Result<DockerStats> {
+    let runtime = runtime::runtime();
+
+    let mut result = DockerStats::default();
+
+    for id in &instance.containers {
+        let stats = runtime.stats(id).await?;
+
+        result.cpu += stats.cpu;
+        result.memory += stats.memory;
+        result.memory_limit += stats.memory_limit;
+        result.network += stats.network;
+    }
+
+    Ok(result)
+}
+
+pub struct Terminal {
+    pub exec: Option<String>,
+    pub output: BoxStream<'static, Result<Vec<u8>>>,
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+// only the first service with a terminal configured is reachable in compose.
+fn terminal_target<'a>(
+    artifact: &'a Artifact,
+    instance: &'a RunDockerResult,
+) -> Option<(&'a TerminalConfig, &'a str, String)> {
+    match artifact {
+        Artifact::Docker(docker) => Some((
+            docker.config.terminal.as_ref()?,
+            instance.containers.first()?,
+            String::new(),
+        )),
+        Artifact::Compose(compose) => {
+            compose
+                .services
+                .iter()
+                .zip(&instance.containers)
+                .find_map(|(service, container)| {
+                    let terminal = service.config.terminal.as_ref()?;
+                    Some((terminal, container.as_str(), format!("{}:", service.name)))
+                })
+        }
+        _ => None,
+    }
+}
+
+pub fn terminal_config(artifact: &Artifact) -> Option<&TerminalConfig> {
+    match artifact {
+        Artifact::Docker(docker) => docker.config.terminal.as_ref(),
+        Artifact::Compose(compose) => compose
+            .services
+            .iter()
+            .find_map(|service| service.config.terminal.as_ref()),
+        _ => None,
+    }
+}
+
+pub async fn open_terminal(artifact: &Artifact, instance: &RunDockerResult) -> Result<Terminal> {
+    let (terminal, container, prefix) = terminal_target(artifact, instance)
+        .ok_or_else(|| anyhow!("terminal is not enabled for the artifact."))?;
+
+    match terminal {
+        TerminalConfig::Tcp { port } => {
+            let addr = instance
+                .upstreams
+                .get(&format!("{prefix}{port}"))
+                .ok_or_else(|| anyhow!("port '{port}' is not exposed."))?;
+
+            let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+
+            Ok(Terminal {
+                exec: None,
+                output: runtime::read_stream(reader),
+                input: Box::pin(writer),
+            })
+        }
+        TerminalConfig::Exec { cmd } => {
+            let session = runtime::runtime().attach_exec(container, cmd).await?;
+
+            Ok(Terminal {
+                exec: Some(session.id),
+                output: session.output,
+                input: session.input,
+            })
+        }
+    }
+}
+
+pub async fn resize_terminal(exec: &str, rows: u16, cols: u16) -> Result<()> {
+    runtime::runtime().resize_exec(exec, rows, cols).await
+}
+
+pub async fn read_docker_logs(instance: &RunDockerResult, tail: usize) -> Result<String> {
+    Ok(capture_logs(runtime::runtime(), instance, &tail.to_string()).await)
+}
+
+pub async fn restart_docker(instance: &RunDockerResult) -> Result<()> {
+    let runtime = runtime::runtime();
+
+    for id in &instance.containers {
+        runtime.restart_container(id).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn remove_orphans(instances: &[RunDockerResult], images: &[String]) -> Result<()> {
+    let runtime = runtime::runtime();
+
+    for id in runtime.list_containers(managed_filters()).await? {
+        if instances.iter().any(|x| x.containers.contains(&id)) {
+            continue;
+        }
+
+        log::info!(target: "conductor", "removing orphan container {id}");
+
+        if let Err(e) = runtime.remove_container(&id).await {
+            log::error!(target: "conductor", "failed to remove orphan container {id}: {e:?}")
+        }
+    }
+
+    for name in runtime.list_networks(managed_filters()).await? {
+        if instances.iter().any(|x| x.network.as_ref() == Some(&name)) {
+            continue;
+        }
+
+        log::info!(target: "conductor", "removing orphan network {name}");
+
+        if let Err(e) = runtime.remove_network(&name).await {
+            log::error!(target: "conductor", "failed to remove orphan network {name}: {e:?}")
+        }
+    }
+
+    for id in runtime.list_images(managed_filters()).await? {
+        if images.contains(&id) {
+            continue;
+        }
+
+        log::info!(target: "conductor", "removing orphan image {id}");
+
+        if let Err(e) = runtime.remove_image(&id).await {
+            log::error!(target: "conductor", "failed to remove orphan image {id}: {e:?}")
+        }
+    }
 
     Ok(())
 }
 
+// artifacts referring to each shared image, the image is removed along with the last of them.
+static SHARED_IMAGES: LazyLock<Mutex<HashMap<String, usize>>> = LazyLock::new(Default::default);
+
+fn shared_images(artifacts: &[Artifact]) -> Vec<&str> {
+    artifacts
+        .iter()
+        .flat_map(|artifact| match artifact {
+            Artifact::Docker(docker) if docker.config.flag.is_some() => vec![docker.id.as_str()],
+            Artifact::Compose(compose) => compose
+                .services
+                .iter()
+                .filter(|service| service.built && service.config.flag.is_some())
+                .map(|service| service.id.as_str())
+                .collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+pub fn retain_shared_images(artifacts: &[Artifact]) {
+    let mut images = SHARED_IMAGES.lock().expect("lock poisoned.");
+
+    for id in shared_images(artifacts) {
+        *images.entry(id.to_string()).or_default() += 1;
+    }
+}
+
+fn release_shared_images(artifacts: &[Artifact]) -> Vec<String> {
+    let mut images = SHARED_IMAGES.lock().expect("lock poisoned.");
+    let mut unused = Vec::new();
+
+    for id in shared_images(artifacts) {
+        let Some(count) = images.get_mut(id) else {
+            continue;
+        };
+
+        *count -= 1;
+
+        if *count == 0 {
+            images.remove(id);
+            unused.push(id.to_string());
+        }
+    }
+
+    unused
+}
+
+async fn remove_docker_artifact(artifact: &DockerArtifact) -> Result<()> {
+    runtime::runtime().remove_image(&artifact.id).await
+}
+
+async fn remove_compose_artifact(artifact: &ComposeArtifact) {
+    for service in artifact
+        .services
+        .iter()
+        .filter(|service| service.built && service.config.flag.is_none())
+    {
+        let artifact = DockerArtifact {
+            id: service.id.clone(),
+            config: service.config.clone(),
+        };
+
+        if let Err(e) = remove_docker_artifact(&artifact).await {
+            log::error!(target: "conductor", "failed to remove compose service image: {e:?}")
+        }
+    }
+}
+
 pub async fn clear_artifact<P: AsRef<Path>>(path: P, artifacts: &[Artifact]) {
     if let Err(e) = fs::remove_dir_all(&path).await {
         log::error!(target: "conductor", "failed to remove dir: {e:?}")
     }
 
     for artifact in artifacts {
-        #[allow(clippy::single_match)]
         match artifact {
+            // shared images may still be in use by others, released below.
+            Artifact::Docker(artifact) if artifact.config.flag.is_some() => {}
             Artifact::Docker(artifact) => {
                 if let Err(e) = remove_docker_artifact(artifact).await {
                     log::error!(target: "conductor", "failed to remove docker artifact: {e:?}")
                 }
             }
+            Artifact::Compose(artifact) => remove_compose_artifact(artifact).await,
             _ => {}
         }
     }
+
+    for id in release_shared_images(artifacts) {
+        if let Err(e) = runtime::runtime().remove_image(&id).await {
+            log::error!(target: "conductor", "failed to remove shared image {id}: {e:?}")
+        }
+    }
 }
 
-pub async fn build<P, Q>(source: P, target: Q, erase: bool, flag: &str) -> Result<BuildResult>
+pub async fn build<P, Q, W>(
+    source: P,
+    target: Q,
+    erase: bool,
+    vars: &BuildVars,
+    limits: &BuildLimits,
+    log: &mut W,
+) -> Result<BuildResult>
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
+    W: AsyncWrite + Unpin,
 {
+    limits.validate()?;
+
+    let ctx = StepContext {
+        vars,
+        limits,
+        deadline: limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout)),
+    };
+
     let tempdir = TempDir::new().await?;
 
     if target.as_ref().exists() {
@@ -541,9 +2082,12 @@ where
 
         for step in &build.steps {
             match step {
-                Step::Cmd(step) => execute_cmd_step(&tempdir, flag, step).await?,
+                Step::Cmd(step) => execute_cmd_step(&tempdir, &ctx, step, log).await?,
                 Step::Docker(step) => artifacts.push(Artifact::Docker(
-                    execute_docker_step(step, &tempdir, flag).await?,
+                    execute_docker_step(step, &tempdir, &ctx, log).await?,
+                )),
+                Step::Compose(step) => artifacts.push(Artifact::Compose(
+                    execute_compose_step(step, &tempdir, &ctx, log).await?,
                 )),
             };
         }
@@ -557,11 +2101,14 @@ where
             }
         }
 
+        retain_shared_images(&artifacts);
+
         Ok(BuildResult { artifacts })
     }
     .await;
 
-    if result.is_err() {
+    if let Err(err) = &result {
+        _ = write_log(log, format!("build failed: {err}\n")).await;
         _ = fs::remove_dir_all(&target).await;
     }
 
@@ -1 +1,3 @@
 pub mod conductor;
+pub mod proxy;
+pub mod runtime;
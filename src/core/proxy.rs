@@ -0,0 +1,373 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+    time,
+};
+
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+const MAX_TOKEN_LINE: usize = 128;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RouteKind {
+    Http,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    pub kind: RouteKind,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Activity {
+    connections: usize,
+    last: Instant,
+}
+
+static ROUTES: LazyLock<RwLock<HashMap<(RouteKind, String), SocketAddr>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+static ACTIVITIES: LazyLock<Mutex<HashMap<(RouteKind, String), Activity>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub async fn add_route(route: &Route, target: SocketAddr) {
+    let key = (route.kind, route.token.clone());
+
+    ROUTES.write().await.insert(key.clone(), target);
+
+    ACTIVITIES.lock().expect("lock poisoned.").insert(
+        key,
+        Activity {
+            connections: 0,
+            last: Instant::now(),
+        },
+    );
+}
+
+pub async fn remove_route(route: &Route) {
+    let key = (route.kind, route.token.clone());
+
+    ROUTES.write().await.remove(&key);
+    ACTIVITIES.lock().expect("lock poisoned.").remove(&key);
+}
+
+async fn get_route(kind: RouteKind, token: &str) -> Option<SocketAddr> {
+    ROUTES.read().await.get(&(kind, token.to_string())).copied()
+}
+
+// a route counts as active for as long as any connection through it stays open.
+pub fn last_activity(route: &Route) -> Option<Instant> {
+    let activities = ACTIVITIES.lock().expect("lock poisoned.");
+    let activity = activities.get(&(route.kind, route.token.clone()))?;
+
+    match activity.connections {
+        0 => Some(activity.last),
+        _ => Some(Instant::now()),
+    }
+}
+
+struct Connection {
+    key: (RouteKind, String),
+}
+
+impl Connection {
+    fn open(kind: RouteKind, token: String) -> Self {
+        let key = (kind, token);
+
+        if let Some(activity) = ACTIVITIES.lock().expect("lock poisoned.").get_mut(&key) {
+            activity.connections += 1;
+            activity.last = Instant::now();
+        }
+
+        Self { key }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(mut activities) = ACTIVITIES.lock() {
+            if let Some(activity) = activities.get_mut(&self.key) {
+                activity.connections = activity.connections.saturating_sub(1);
+                activity.last = Instant::now();
+            }
+        }
+    }
+}
+
+async fn splice(client: &mut TcpStream, mut upstream: TcpStream, head: &[u8]) -> Result<()> {
+    upstream.write_all(head).await?;
+    io::copy_bidirectional(client, &mut upstream).await?;
+
+    Ok(())
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+async fn read_head(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut buf = [0; 4096];
+
+    loop {
+        let len = stream.read(&mut buf).await?;
+
+        if len == 0 {
+            bail!("connection closed before request head.");
+        }
+
+        head.extend_from_slice(&buf[..len]);
+
+        if head.windows(4).any(|x| x == b"\r\n\r\n") {
+            return Ok(head);
+        }
+
+        if head.len() > MAX_HEAD_SIZE {
+            bail!("request head too large.");
+        }
+    }
+}
+
+fn parse_host(head: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(head);
+
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+
+        if !name.trim().eq_ignore_ascii_case("host") {
+            return None;
+        }
+
+        let host = value.trim();
+        let host = host.rsplit_once(':').map(|(x, _)| x).unwrap_or(host);
+
+        Some(host.to_ascii_lowercase())
+    })
+}
+
+async fn handle_http(mut client: TcpStream, domain: &str) -> Result<()> {
+    let head = match time::timeout(HEAD_TIMEOUT, read_head(&mut client)).await {
+        Ok(head) => head?,
+        Err(_) => bail!("timed out reading request head."),
+    };
+
+    let token = parse_host(&head).and_then(|host| {
+        host.strip_suffix(domain)
+            .and_then(|x| x.strip_suffix('.'))
+            .map(|x| x.to_string())
+    });
+
+    let target = match &token {
+        Some(token) => get_route(RouteKind::Http, token).await,
+        None => None,
+    };
+
+    let (Some(token), Some(target)) = (token, target) else {
+        return respond(&mut client, "404 Not Found", "instance not found.").await;
+    };
+
+    let Ok(upstream) = TcpStream::connect(target).await else {
+        return respond(&mut client, "502 Bad Gateway", "instance unreachable.").await;
+    };
+
+    let _connection = Connection::open(RouteKind::Http, token);
+
+    // browsers never reuse a connection across hosts, so the whole connection
+    // (including upgrades like WebSocket) can be piped to the same instance.
+    splice(&mut client, upstream, &head).await
+}
+
+pub async fn serve_http(listen: SocketAddr, domain: String) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let domain = domain.to_ascii_lowercase();
+
+    log::info!(target: "proxy", "http proxy listening on {listen} for *.{domain}");
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!(target: "proxy", "failed to accept connection: {e:?}");
+                continue;
+            }
+        };
+
+        let domain = domain.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_http(stream, &domain).await {
+                log::debug!(target: "proxy", "connection closed: {e:?}");
+            }
+        });
+    }
+}
+
+fn parse_sni(hello: &[u8]) -> Option<String> {
+    // TLS record header (5) + handshake header (4) + version (2) + random (32).
+    let mut pos = 5 + 4 + 2 + 32;
+
+    let session_id = *hello.get(pos)? as usize;
+    pos += 1 + session_id;
+
+    let cipher_suites = u16::from_be_bytes([*hello.get(pos)?, *hello.get(pos + 1)?]) as usize;
+    pos += 2 + cipher_suites;
+
+    let compression = *hello.get(pos)? as usize;
+    pos += 1 + compression;
+
+    let extensions = u16::from_be_bytes([*hello.get(pos)?, *hello.get(pos + 1)?]) as usize;
+    pos += 2;
+
+    let end = (pos + extensions).min(hello.len());
+
+    while pos + 4 <= end {
+        let kind = u16::from_be_bytes([hello[pos], hello[pos + 1]]);
+        let len = u16::from_be_bytes([hello[pos + 2], hello[pos + 3]]) as usize;
+        pos += 4;
+
+        if kind == 0 {
+            // server name list length (2) + name type (1) + name length (2).
+            let name_len = u16::from_be_bytes([*hello.get(pos + 3)?, *hello.get(pos + 4)?]);
+            let name = hello.get(pos + 5..pos + 5 + name_len as usize)?;
+
+            return Some(String::from_utf8_lossy(name).to_ascii_lowercase());
+        }
+
+        pos += len;
+    }
+
+    None
+}
+
+async fn read_hello(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut hello = vec![0; 5];
+    stream.read_exact(&mut hello).await?;
+
+    let len = u16::from_be_bytes([hello[3], hello[4]]) as usize;
+
+    if len > MAX_HEAD_SIZE {
+        bail!("client hello too large.");
+    }
+
+    hello.resize(5 + len, 0);
+    stream.read_exact(&mut hello[5..]).await?;
+
+    Ok(hello)
+}
+
+async fn read_line(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+
+    // read byte by byte to leave anything after the token to the instance.
+    while line.last() != Some(&b'\n') {
+        if stream.read(&mut byte).await? == 0 {
+            bail!("connection closed before token.");
+        }
+
+        line.push(byte[0]);
+
+        if line.len() > MAX_TOKEN_LINE {
+            bail!("token line too long.");
+        }
+    }
+
+    Ok(line)
+}
+
+async fn handle_tcp(mut client: TcpStream, domain: Option<&str>) -> Result<()> {
+    let mut first = [0; 1];
+
+    if client.peek(&mut first).await? == 0 {
+        return Ok(());
+    }
+
+    // 0x16 is the content type of a TLS handshake record.
+    let (token, head) = match first[0] {
+        0x16 => {
+            let hello = match time::timeout(HEAD_TIMEOUT, read_hello(&mut client)).await {
+                Ok(hello) => hello?,
+                Err(_) => bail!("timed out reading client hello."),
+            };
+
+            let token = parse_sni(&hello).and_then(|host| match domain {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .and_then(|x| x.strip_suffix('.'))
+                    .map(|x| x.to_string()),
+                None => host.split('.').next().map(|x| x.to_string()),
+            });
+
+            (token, hello)
+        }
+        _ => {
+            let line = match time::timeout(HEAD_TIMEOUT, read_line(&mut client)).await {
+                Ok(line) => line?,
+                Err(_) => bail!("timed out reading token."),
+            };
+
+            let token = String::from_utf8_lossy(&line).trim().to_string();
+
+            (Some(token), Vec::new())
+        }
+    };
+
+    let target = match &token {
+        Some(token) => get_route(RouteKind::Tcp, token).await,
+        None => None,
+    };
+
+    let (Some(token), Some(target)) = (token, target) else {
+        client.write_all(b"instance not found.\n").await?;
+        return Ok(());
+    };
+
+    let upstream = TcpStream::connect(target).await?;
+    let _connection = Connection::open(RouteKind::Tcp, token);
+
+    splice(&mut client, upstream, &head).await
+}
+
+pub async fn serve_tcp(listen: SocketAddr, domain: Option<String>) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let domain = domain.map(|x| x.to_ascii_lowercase());
+
+    log::info!(target: "proxy", "tcp gateway listening on {listen}");
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!(target: "proxy", "failed to accept connection: {e:?}");
+                continue;
+            }
+        };
+
+        let domain = domain.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_tcp(stream, domain.as_deref()).await {
+                log::debug!(target: "proxy", "connection closed: {e:?}");
+            }
+        });
+    }
+}
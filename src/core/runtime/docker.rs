@@ -0,0 +1,609 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::{anyhow, bail, Result};
+use bollard::{
+    container::{
+        Config as ContainerConfig, ListContainersOptions, LogsOptions, NetworkingConfig,
+        RemoveContainerOptions, RestartContainerOptions, StartContainerOptions, Stats,
+        StatsOptions, WaitContainerOptions,
+    },
+    errors::Error as DockerError,
+    exec::{CreateExecOptions, ResizeExecOptions, StartExecResults},
+    image::{
+        BuildImageOptions, BuilderVersion, CreateImageOptions, ListImagesOptions,
+        RemoveImageOptions,
+    },
+    moby::buildkit::v1::StatusResponse,
+    network::{CreateNetworkOptions, ListNetworksOptions},
+    secret::{
+        BuildInfo, BuildInfoAux, ContainerInspectResponse, EndpointSettings, HostConfig,
+        PortBinding, ResourcesUlimits,
+    },
+    Docker,
+};
+use futures_util::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt, TryStreamExt,
+};
+
+use super::{
+    BuildEvent, ContainerInfo, ContainerSpec, ContainerStats, ExecSession, Filters, ImageInfo,
+    ImageSpec, PortConflict, Runtime,
+};
+
+#[derive(Default)]
+pub struct DockerRuntime {
+    docker: OnceLock<Docker>,
+}
+
+impl DockerRuntime {
+    fn client(&self) -> Result<&Docker> {
+        if let Some(docker) = self.docker.get() {
+            return Ok(docker);
+        }
+
+        let docker = Docker::connect_with_defaults()?;
+        Ok(self.docker.get_or_init(|| docker))
+    }
+}
+
+fn is_not_found(err: &DockerError) -> bool {
+    matches!(
+        err,
+        DockerError::DockerResponseServerError {
+            status_code: 404,
+            ..
+        }
+    )
+}
+
+// docker reports a taken host port when the container is started.
+fn map_port_conflict(err: DockerError) -> anyhow::Error {
+    match &err {
+        DockerError::DockerResponseServerError { message, .. }
+            if message.contains("port is already allocated")
+                || message.contains("address already in use") =>
+        {
+            PortConflict(message.clone()).into()
+        }
+        _ => err.into(),
+    }
+}
+
+fn build_options(spec: ImageSpec) -> BuildImageOptions<String> {
+    BuildImageOptions {
+        buildargs: spec.args,
+        t: spec.tag,
+        dockerfile: "Dockerfile".to_string(),
+        session: Some(uuid::Uuid::new_v4().as_simple().to_string()),
+        version: BuilderVersion::BuilderBuildKit,
+        cpuperiod: spec.cpus.map(|_| 100000),
+        cpuquota: spec.cpus.map(|x| (x * 100000.0).round() as u64),
+        memory: spec.memory.map(|x| x as u64),
+        networkmode: spec.network,
+        labels: spec.labels,
+        ..Default::default()
+    }
+}
+
+fn buildkit_log(status: &StatusResponse) -> String {
+    let mut log = String::new();
+
+    for vertex in &status.vertexes {
+        if !vertex.error.is_empty() {
+            log += &format!("=> {} ERROR: {}\n", vertex.name, vertex.error);
+        } else if vertex.cached {
+            log += &format!("=> {} CACHED\n", vertex.name);
+        } else if vertex.completed.is_some() {
+            log += &format!("=> {} DONE\n", vertex.name);
+        }
+    }
+
+    for vertex_log in &status.logs {
+        log += &String::from_utf8_lossy(&vertex_log.msg);
+    }
+
+    log
+}
+
+fn build_event(info: BuildInfo) -> BuildEvent {
+    let mut event = BuildEvent {
+        log: info.stream,
+        error: info.error,
+        id: None,
+    };
+
+    match info.aux {
+        Some(BuildInfoAux::BuildKit(status)) => {
+            event.log = Some(event.log.unwrap_or_default() + &buildkit_log(&status));
+
+            if let Some(vertex) = status.vertexes.iter().find(|x| !x.error.is_empty()) {
+                event.error = Some(vertex.error.clone());
+            }
+        }
+        Some(BuildInfoAux::Default(inner)) => event.id = inner.id,
+        None => {}
+    }
+
+    event
+}
+
+fn container_config(spec: ContainerSpec) -> ContainerConfig<String> {
+    let port_bindings = spec
+        .ports
+        .into_iter()
+        .map(|(exposed, addrs)| {
+            let bindings = addrs
+                .iter()
+                .map(|addr| PortBinding {
+                    host_ip: Some(addr.ip().to_string()),
+                    host_port: Some(addr.port().to_string()),
+                })
+                .collect();
+
+            (exposed, Some(bindings))
+        })
+        .collect();
+
+    let host_config = HostConfig {
+        binds: Some(spec.binds),
+        port_bindings: Some(port_bindings),
+        publish_all_ports: Some(false),
+        network_mode: spec.network.clone(),
+        cpu_quota: spec.cpus.map(|x| (x * 100000.0).round() as i64),
+        memory: spec.memory,
+        pids_limit: spec.pids,
+        storage_opt: spec
+            .storage
+            .map(|size| HashMap::from([("size".to_string(), size)])),
+        privileged: Some(spec.privileged),
+        cap_add: Some(spec.cap_add),
+        cap_drop: Some(spec.cap_drop),
+        readonly_rootfs: Some(spec.read_only),
+        tmpfs: Some(spec.tmpfs),
+        security_opt: Some(spec.security_opt),
+        ulimits: Some(
+            spec.ulimits
+                .into_iter()
+                .map(|ulimit| ResourcesUlimits {
+                    name: Some(ulimit.name),
+                    soft: Some(ulimit.soft),
+                    hard: Some(ulimit.hard),
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    };
+
+    let networking_config = match (&spec.network, spec.aliases.is_empty()) {
+        (Some(network), false) => {
+            let endpoint = EndpointSettings {
+                aliases: Some(spec.aliases),
+                ..Default::default()
+            };
+
+            Some(NetworkingConfig {
+                endpoints_config: HashMap::from([(network.clone(), endpoint)]),
+            })
+        }
+        _ => None,
+    };
+
+    ContainerConfig {
+        image: Some(spec.image),
+        cmd: spec.cmd,
+        env: Some(spec.env),
+        working_dir: spec.working_dir,
+        labels: Some(spec.labels),
+        exposed_ports: Some(
+            spec.exposed
+                .into_iter()
+                .map(|exposed| (exposed, HashMap::new()))
+                .collect(),
+        ),
+        host_config: Some(host_config),
+        networking_config,
+        ..Default::default()
+    }
+}
+
+fn container_info(inspect: ContainerInspectResponse) -> ContainerInfo {
+    let state = inspect.state.unwrap_or_default();
+
+    let addrs = inspect
+        .network_settings
+        .and_then(|settings| settings.networks)
+        .into_iter()
+        .flatten()
+        .filter_map(|(network, endpoint)| Some((network, endpoint.ip_address?.parse().ok()?)))
+        .collect();
+
+    ContainerInfo {
+        running: state.running.unwrap_or_default(),
+        exit_code: state.exit_code,
+        error: state.error.filter(|error| !error.is_empty()),
+        addrs,
+    }
+}
+
+fn cpu_percent(stats: &Stats) -> f64 {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+
+    let system_delta = stats
+        .cpu_stats
+        .system_cpu_usage
+        .unwrap_or_default()
+        .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or_default());
+
+    if system_delta == 0 {
+        return 0.0;
+    }
+
+    let cpus = stats.cpu_stats.online_cpus.unwrap_or(1);
+
+    cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+}
+
+impl Runtime for DockerRuntime {
+    fn pull_image<'a>(&'a self, image: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let options = CreateImageOptions::<&str> {
+                from_image: image,
+                ..Default::default()
+            };
+
+            let mut stream = self.client()?.create_image(Some(options), None, None);
+            let mut info = None;
+
+            while let Some(Ok(inner)) = stream.next().await {
+                info = Some(inner)
+            }
+
+            let info = info.ok_or_else(|| anyhow!("no response from create_image"))?;
+
+            if let Some(err) = info.error {
+                bail!("pull image failed: {err}");
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn build_image(&self, spec: ImageSpec, context: Vec<u8>) -> BoxStream<'_, Result<BuildEvent>> {
+        match self.client() {
+            Ok(docker) => docker
+                .build_image(build_options(spec), None, Some(context.into()))
+                .map_ok(build_event)
+                .map_err(Into::into)
+                .boxed(),
+            Err(err) => stream::once(async { Err(err) }).boxed(),
+        }
+    }
+
+    fn inspect_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<ImageInfo>>> {
+        async move {
+            match self.client()?.inspect_image(name).await {
+                Ok(image) => Ok(Some(ImageInfo {
+                    id: image.id.ok_or_else(|| anyhow!("no image id got."))?,
+                })),
+                Err(err) if is_not_found(&err) => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list_images(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let options = ListImagesOptions {
+                filters,
+                ..Default::default()
+            };
+
+            let images = self.client()?.list_images(Some(options)).await?;
+
+            Ok(images.into_iter().map(|image| image.id).collect())
+        }
+        .boxed()
+    }
+
+    fn remove_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let options = RemoveImageOptions {
+                force: true,
+                ..Default::default()
+            };
+
+            self.client()?
+                .remove_image(name, Some(options), None)
+                .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn create_container(&self, spec: ContainerSpec) -> BoxFuture<'_, Result<String>> {
+        async move {
+            let created = self
+                .client()?
+                .create_container::<String, _>(None, container_config(spec))
+                .await
+                .map_err(map_port_conflict)?;
+
+            Ok(created.id)
+        }
+        .boxed()
+    }
+
+    fn start_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.client()?
+                .start_container(id, None::<StartContainerOptions<&str>>)
+                .await
+                .map_err(map_port_conflict)?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn wait_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut stream = self
+                .client()?
+                .wait_container(id, None::<WaitContainerOptions<&str>>);
+            let mut resp = None;
+
+            while let Some(Ok(inner)) = stream.next().await {
+                resp = Some(inner);
+            }
+
+            if let Some(err) = resp.and_then(|resp| resp.error) {
+                bail!("wait container error: {}", err.message.unwrap_or_default());
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn restart_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.client()?
+                .restart_container(id, Some(RestartContainerOptions { t: 10 }))
+                .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let options = RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            };
+
+            self.client()?.remove_container(id, Some(options)).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn inspect_container<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<ContainerInfo>>> {
+        async move {
+            match self.client()?.inspect_container(id, None).await {
+                Ok(inspect) => Ok(Some(container_info(inspect))),
+                Err(err) if is_not_found(&err) => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list_containers(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let options = ListContainersOptions {
+                all: true,
+                filters,
+                ..Default::default()
+            };
+
+            let containers = self.client()?.list_containers(Some(options)).await?;
+
+            Ok(containers
+                .into_iter()
+                .filter_map(|container| container.id)
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn logs<'a>(
+        &'a self,
+        id: &'a str,
+        follow: bool,
+        tail: &'a str,
+    ) -> BoxStream<'a, Result<Vec<u8>>> {
+        let options = LogsOptions {
+            follow,
+            stdout: true,
+            stderr: true,
+            tail,
+            ..Default::default()
+        };
+
+        match self.client() {
+            Ok(docker) => docker
+                .logs(id, Some(options))
+                .map_ok(|output| output.as_ref().to_vec())
+                .map_err(Into::into)
+                .boxed(),
+            Err(err) => stream::once(async { Err(err) }).boxed(),
+        }
+    }
+
+    fn stats<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<ContainerStats>> {
+        async move {
+            let options = StatsOptions {
+                stream: false,
+                one_shot: false,
+            };
+
+            let stats = self
+                .client()?
+                .stats(id, Some(options))
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("no stats returned for container {id}."))??;
+
+            Ok(ContainerStats {
+                cpu: cpu_percent(&stats),
+                memory: stats.memory_stats.usage.unwrap_or_default(),
+                memory_limit: stats.memory_stats.limit.unwrap_or_default(),
+                network: stats
+                    .networks
+                    .iter()
+                    .flatten()
+                    .map(|(_, network)| network.rx_bytes + network.tx_bytes)
+                    .sum(),
+            })
+        }
+        .boxed()
+    }
+
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [String]) -> BoxFuture<'a, Result<Option<i64>>> {
+        async move {
+            let docker = self.client()?;
+
+            let options = CreateExecOptions {
+                cmd: Some(cmd.to_vec()),
+                ..Default::default()
+            };
+
+            let exec = docker.create_exec(id, options).await?;
+
+            if let StartExecResults::Attached { mut output, .. } =
+                docker.start_exec(&exec.id, None).await?
+            {
+                while output.next().await.is_some() {}
+            }
+
+            let inspect = docker.inspect_exec(&exec.id).await?;
+
+            Ok(inspect.exit_code)
+        }
+        .boxed()
+    }
+
+    fn attach_exec<'a>(
+        &'a self,
+        id: &'a str,
+        cmd: &'a [String],
+    ) -> BoxFuture<'a, Result<ExecSession>> {
+        async move {
+            let docker = self.client()?;
+
+            let options = CreateExecOptions {
+                cmd: Some(cmd.to_vec()),
+                attach_stdin: Some(true),
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                tty: Some(true),
+                ..Default::default()
+            };
+
+            let exec = docker.create_exec(id, options).await?;
+
+            let StartExecResults::Attached { output, input } =
+                docker.start_exec(&exec.id, None).await?
+            else {
+                bail!("exec {} is not attached.", exec.id);
+            };
+
+            Ok(ExecSession {
+                id: exec.id,
+                output: output
+                    .map_ok(|output| output.into_bytes().to_vec())
+                    .map_err(Into::into)
+                    .boxed(),
+                input,
+            })
+        }
+        .boxed()
+    }
+
+    fn resize_exec<'a>(&'a self, exec: &'a str, rows: u16, cols: u16) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let options = ResizeExecOptions {
+                height: rows,
+                width: cols,
+            };
+
+            self.client()?.resize_exec(exec, options).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn create_network<'a>(
+        &'a self,
+        name: &'a str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let options = CreateNetworkOptions {
+                name,
+                driver: "bridge",
+                check_duplicate: true,
+                internal,
+                labels: labels
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+                    .collect(),
+                ..Default::default()
+            };
+
+            self.client()?.create_network(options).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_network<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.client()?.remove_network(name).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list_networks(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let options = ListNetworksOptions { filters };
+            let networks = self.client()?.list_networks(Some(options)).await?;
+
+            Ok(networks
+                .into_iter()
+                .filter_map(|network| network.name)
+                .collect())
+        }
+        .boxed()
+    }
+}
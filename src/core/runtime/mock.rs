@@ -0,0 +1,554 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt, DuplexStream},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+use super::{
+    read_stream, BuildEvent, ContainerInfo, ContainerSpec, ContainerStats, ExecSession, Filters,
+    ImageInfo, ImageSpec, Runtime,
+};
+
+struct MockImage {
+    id: String,
+    tags: Vec<String>,
+    labels: HashMap<String, String>,
+}
+
+struct MockContainer {
+    spec: ContainerSpec,
+    running: bool,
+    exit_code: Option<i64>,
+    logs: Vec<String>,
+    listeners: Vec<JoinHandle<()>>,
+    traffic: Arc<AtomicU64>,
+}
+
+struct MockNetwork {
+    labels: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    images: Vec<MockImage>,
+    containers: HashMap<String, MockContainer>,
+    networks: HashMap<String, MockNetwork>,
+}
+
+// an in-memory runtime for exercising the platform without a docker daemon.
+// containers run nothing, published tcp ports answer with a fixed http response.
+#[derive(Default)]
+pub struct MockRuntime {
+    state: Arc<Mutex<MockState>>,
+}
+
+fn random_id() -> String {
+    uuid::Uuid::new_v4().as_simple().to_string()
+}
+
+fn matches(labels: &HashMap<String, String>, filters: &Filters) -> bool {
+    filters
+        .get("label")
+        .into_iter()
+        .flatten()
+        .all(|filter| match filter.split_once('=') {
+            Some((key, value)) => labels.get(key).is_some_and(|x| x == value),
+            None => labels.contains_key(filter),
+        })
+}
+
+async fn serve(listener: TcpListener, id: String, traffic: Arc<AtomicU64>) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let response = format!(
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nmock container {id}\n"
+        );
+
+        traffic.fetch_add(response.len() as u64, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            _ = stream.write_all(response.as_bytes()).await;
+            _ = stream.shutdown().await;
+        });
+    }
+}
+
+// stands in for an interactive shell, emulating the echo of a tty.
+async fn echo_shell(stream: DuplexStream, id: String) {
+    let (mut reader, mut writer) = io::split(stream);
+    let banner = format!("mock shell of container {id}, input is echoed back.\r\n$ ");
+
+    if writer.write_all(banner.as_bytes()).await.is_err() {
+        return;
+    }
+
+    let mut buf = [0; 1024];
+
+    while let Ok(len @ 1..) = reader.read(&mut buf).await {
+        let echoed = String::from_utf8_lossy(&buf[..len]).replace('\r', "\r\n$ ");
+
+        if writer.write_all(echoed.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+impl MockRuntime {
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().expect("lock poisoned.")
+    }
+
+    fn stop(container: &mut MockContainer) {
+        for listener in container.listeners.drain(..) {
+            listener.abort();
+        }
+
+        container.running = false;
+    }
+}
+
+impl Runtime for MockRuntime {
+    fn pull_image<'a>(&'a self, image: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            if !state
+                .images
+                .iter()
+                .any(|x| x.tags.iter().any(|tag| tag == image))
+            {
+                state.images.push(MockImage {
+                    id: format!("sha256:{}", random_id()),
+                    tags: vec![image.to_string()],
+                    labels: HashMap::new(),
+                });
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn build_image(&self, spec: ImageSpec, _context: Vec<u8>) -> BoxStream<'_, Result<BuildEvent>> {
+        let id = format!("sha256:{}", random_id());
+
+        self.lock().images.push(MockImage {
+            id: id.clone(),
+            tags: vec![spec.tag.clone()],
+            labels: spec.labels,
+        });
+
+        let events = vec![
+            Ok(BuildEvent {
+                log: Some(format!("mock build of {}\n", spec.tag)),
+                ..Default::default()
+            }),
+            Ok(BuildEvent {
+                id: Some(id),
+                ..Default::default()
+            }),
+        ];
+
+        stream::iter(events).boxed()
+    }
+
+    fn inspect_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<ImageInfo>>> {
+        async move {
+            let state = self.lock();
+
+            let image = state
+                .images
+                .iter()
+                .find(|x| x.id == name || x.tags.iter().any(|tag| tag == name))
+                .map(|image| ImageInfo {
+                    id: image.id.clone(),
+                });
+
+            Ok(image)
+        }
+        .boxed()
+    }
+
+    fn list_images(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let state = self.lock();
+
+            let images = state
+                .images
+                .iter()
+                .filter(|image| matches(&image.labels, &filters))
+                .map(|image| image.id.clone())
+                .collect();
+
+            Ok(images)
+        }
+        .boxed()
+    }
+
+    fn remove_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            let len = state.images.len();
+            state
+                .images
+                .retain(|x| x.id != name && !x.tags.iter().any(|tag| tag == name));
+
+            if state.images.len() == len {
+                return Err(anyhow!("no such image: {name}"));
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn create_container(&self, spec: ContainerSpec) -> BoxFuture<'_, Result<String>> {
+        async move {
+            let mut state = self.lock();
+
+            if !state
+                .images
+                .iter()
+                .any(|x| x.id == spec.image || x.tags.contains(&spec.image))
+            {
+                return Err(anyhow!("no such image: {}", spec.image));
+            }
+
+            let id = random_id();
+
+            state.containers.insert(
+                id.clone(),
+                MockContainer {
+                    spec,
+                    running: false,
+                    exit_code: None,
+                    logs: vec![format!("mock container {id} created\n")],
+                    listeners: Vec::new(),
+                    traffic: Default::default(),
+                },
+            );
+
+            Ok(id)
+        }
+        .boxed()
+    }
+
+    fn start_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let (addrs, traffic) = {
+                let state = self.lock();
+
+                let container = state
+                    .containers
+                    .get(id)
+                    .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+                let addrs: Vec<_> = container
+                    .spec
+                    .ports
+                    .iter()
+                    .filter(|(exposed, _)| !exposed.ends_with("/udp"))
+                    .flat_map(|(_, addrs)| addrs.iter().copied())
+                    .collect();
+
+                (addrs, container.traffic.clone())
+            };
+
+            let mut listeners = Vec::new();
+            let mut bound = HashSet::new();
+
+            for addr in addrs {
+                let listener = match TcpListener::bind(addr).await {
+                    Ok(listener) => listener,
+                    // a dual-stack `::` listener already covers `0.0.0.0` on the same port.
+                    Err(err)
+                        if err.kind() == ErrorKind::AddrInUse && bound.contains(&addr.port()) =>
+                    {
+                        continue
+                    }
+                    Err(err) => {
+                        listeners.iter().for_each(JoinHandle::abort);
+                        return Err(err.into());
+                    }
+                };
+
+                bound.insert(addr.port());
+                listeners.push(tokio::spawn(serve(
+                    listener,
+                    id.to_string(),
+                    traffic.clone(),
+                )));
+            }
+
+            let mut state = self.lock();
+
+            let container = state
+                .containers
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+            container.running = true;
+            container.listeners = listeners;
+            container
+                .logs
+                .push("mock runtime does not execute any command\n".to_string());
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn wait_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            let container = state
+                .containers
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+            Self::stop(container);
+            container.exit_code = Some(0);
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn restart_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            let container = state
+                .containers
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+            container
+                .logs
+                .push("mock container restarted\n".to_string());
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            let mut container = state
+                .containers
+                .remove(id)
+                .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+            Self::stop(&mut container);
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn inspect_container<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<ContainerInfo>>> {
+        async move {
+            let state = self.lock();
+
+            let Some(container) = state.containers.get(id) else {
+                return Ok(None);
+            };
+
+            let addrs = container
+                .spec
+                .network
+                .iter()
+                .map(|network| (network.clone(), IpAddr::V4(Ipv4Addr::LOCALHOST)))
+                .collect();
+
+            Ok(Some(ContainerInfo {
+                running: container.running,
+                exit_code: container.exit_code,
+                error: None,
+                addrs,
+            }))
+        }
+        .boxed()
+    }
+
+    fn list_containers(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let state = self.lock();
+
+            let containers = state
+                .containers
+                .iter()
+                .filter(|(_, container)| matches(&container.spec.labels, &filters))
+                .map(|(id, _)| id.clone())
+                .collect();
+
+            Ok(containers)
+        }
+        .boxed()
+    }
+
+    fn logs<'a>(
+        &'a self,
+        id: &'a str,
+        _follow: bool,
+        tail: &'a str,
+    ) -> BoxStream<'a, Result<Vec<u8>>> {
+        let logs = match self.lock().containers.get(id) {
+            Some(container) => container.logs.clone(),
+            None => {
+                return stream::once(async move { Err(anyhow!("no such container: {id}")) }).boxed()
+            }
+        };
+
+        let skip = tail
+            .parse::<usize>()
+            .map(|tail| logs.len().saturating_sub(tail))
+            .unwrap_or_default();
+
+        stream::iter(
+            logs.into_iter()
+                .skip(skip)
+                .map(|line| Ok(line.into_bytes())),
+        )
+        .boxed()
+    }
+
+    fn stats<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<ContainerStats>> {
+        async move {
+            let state = self.lock();
+
+            let container = state
+                .containers
+                .get(id)
+                .ok_or_else(|| anyhow!("no such container: {id}"))?;
+
+            Ok(ContainerStats {
+                cpu: 0.0,
+                memory: 0,
+                memory_limit: container.spec.memory.unwrap_or_default() as u64,
+                network: container.traffic.load(Ordering::Relaxed),
+            })
+        }
+        .boxed()
+    }
+
+    fn exec<'a>(&'a self, id: &'a str, _cmd: &'a [String]) -> BoxFuture<'a, Result<Option<i64>>> {
+        async move {
+            let state = self.lock();
+
+            match state.containers.get(id) {
+                Some(container) if container.running => Ok(Some(0)),
+                Some(_) => Err(anyhow!("container {id} is not running")),
+                None => Err(anyhow!("no such container: {id}")),
+            }
+        }
+        .boxed()
+    }
+
+    fn attach_exec<'a>(
+        &'a self,
+        id: &'a str,
+        _cmd: &'a [String],
+    ) -> BoxFuture<'a, Result<ExecSession>> {
+        async move {
+            match self.lock().containers.get(id) {
+                Some(container) if container.running => {}
+                Some(_) => return Err(anyhow!("container {id} is not running")),
+                None => return Err(anyhow!("no such container: {id}")),
+            }
+
+            let (client, server) = io::duplex(4096);
+            tokio::spawn(echo_shell(server, id.to_string()));
+
+            let (reader, writer) = io::split(client);
+
+            Ok(ExecSession {
+                id: random_id(),
+                output: read_stream(reader),
+                input: Box::pin(writer),
+            })
+        }
+        .boxed()
+    }
+
+    fn resize_exec<'a>(
+        &'a self,
+        _exec: &'a str,
+        _rows: u16,
+        _cols: u16,
+    ) -> BoxFuture<'a, Result<()>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn create_network<'a>(
+        &'a self,
+        name: &'a str,
+        _internal: bool,
+        labels: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut state = self.lock();
+
+            if state.networks.contains_key(name) {
+                return Err(anyhow!("network {name} already exists"));
+            }
+
+            state
+                .networks
+                .insert(name.to_string(), MockNetwork { labels });
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_network<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.lock()
+                .networks
+                .remove(name)
+                .ok_or_else(|| anyhow!("no such network: {name}"))?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list_networks(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let state = self.lock();
+
+            let networks = state
+                .networks
+                .iter()
+                .filter(|(_, network)| matches(&network.labels, &filters))
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            Ok(networks)
+        }
+        .boxed()
+    }
+}
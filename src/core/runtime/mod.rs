@@ -0,0 +1,213 @@
+mod docker;
+#[cfg(test)]
+mod mock;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::OnceLock,
+};
+
+use anyhow::Result;
+use futures_util::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+pub use docker::DockerRuntime;
+#[cfg(test)]
+pub use mock::MockRuntime;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContainerStats {
+    pub cpu: f64,
+    pub memory: u64,
+    pub memory_limit: u64,
+    pub network: u64,
+}
+
+pub type Filters = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct ImageSpec {
+    pub tag: String,
+    pub args: HashMap<String, String>,
+    pub labels: HashMap<String, String>,
+    pub cpus: Option<f64>,
+    pub memory: Option<i64>,
+    pub network: String,
+}
+
+// a progress update of an image build, the id is reported once the image is built.
+#[derive(Debug, Clone, Default)]
+pub struct BuildEvent {
+    pub log: Option<String>,
+    pub error: Option<String>,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImageInfo {
+    pub id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContainerSpec {
+    pub image: String,
+    pub cmd: Option<Vec<String>>,
+    pub env: Vec<String>,
+    pub working_dir: Option<String>,
+    pub labels: HashMap<String, String>,
+    pub exposed: Vec<String>,
+    pub binds: Vec<String>,
+    // exposed ports and the host addresses they are published on.
+    pub ports: HashMap<String, Vec<SocketAddr>>,
+    pub network: Option<String>,
+    pub aliases: Vec<String>,
+    pub cpus: Option<f64>,
+    pub memory: Option<i64>,
+    pub pids: Option<i64>,
+    pub storage: Option<String>,
+    pub privileged: bool,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub read_only: bool,
+    pub tmpfs: HashMap<String, String>,
+    pub security_opt: Vec<String>,
+    pub ulimits: Vec<Ulimit>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContainerInfo {
+    pub running: bool,
+    pub exit_code: Option<i64>,
+    pub error: Option<String>,
+    // addresses of the container keyed by network.
+    pub addrs: HashMap<String, IpAddr>,
+}
+
+// a published port is already taken on the host.
+#[derive(Debug)]
+pub struct PortConflict(pub String);
+
+impl fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PortConflict {}
+
+// an interactive process attached to a tty inside a container.
+pub struct ExecSession {
+    pub id: String,
+    pub output: BoxStream<'static, Result<Vec<u8>>>,
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+pub fn read_stream<R>(reader: R) -> BoxStream<'static, Result<Vec<u8>>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    stream::unfold(reader, |mut reader| async move {
+        let mut buf = vec![0; 4096];
+
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(len) => {
+                buf.truncate(len);
+                Some((Ok(buf), reader))
+            }
+            Err(err) => Some((Err(err.into()), reader)),
+        }
+    })
+    .boxed()
+}
+
+pub trait Runtime: Send + Sync {
+    fn pull_image<'a>(&'a self, image: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn build_image(&self, spec: ImageSpec, context: Vec<u8>) -> BoxStream<'_, Result<BuildEvent>>;
+
+    fn inspect_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<ImageInfo>>>;
+
+    // ids of the matching images.
+    fn list_images(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn remove_image<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn create_container(&self, spec: ContainerSpec) -> BoxFuture<'_, Result<String>>;
+
+    fn start_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn wait_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn restart_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn remove_container<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn inspect_container<'a>(&'a self, id: &'a str)
+        -> BoxFuture<'a, Result<Option<ContainerInfo>>>;
+
+    // ids of the matching containers, stopped ones included.
+    fn list_containers(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn logs<'a>(
+        &'a self,
+        id: &'a str,
+        follow: bool,
+        tail: &'a str,
+    ) -> BoxStream<'a, Result<Vec<u8>>>;
+
+    fn stats<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<ContainerStats>>;
+
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [String]) -> BoxFuture<'a, Result<Option<i64>>>;
+
+    fn attach_exec<'a>(
+        &'a self,
+        id: &'a str,
+        cmd: &'a [String],
+    ) -> BoxFuture<'a, Result<ExecSession>>;
+
+    fn resize_exec<'a>(&'a self, exec: &'a str, rows: u16, cols: u16) -> BoxFuture<'a, Result<()>>;
+
+    fn create_network<'a>(
+        &'a self,
+        name: &'a str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    fn remove_network<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    // names of the matching networks.
+    fn list_networks(&self, filters: Filters) -> BoxFuture<'_, Result<Vec<String>>>;
+}
+
+static RUNTIME: OnceLock<Box<dyn Runtime>> = OnceLock::new();
+
+// tests swap in the mock runtime before anything touches docker.
+#[cfg(test)]
+pub fn init(runtime: Box<dyn Runtime>) {
+    if RUNTIME.set(runtime).is_err() {
+        log::warn!(target: "runtime", "container runtime is already initialized.");
+    }
+}
+
+pub fn runtime() -> &'static dyn Runtime {
+    RUNTIME
+        .get_or_init(|| Box::new(DockerRuntime::default()))
+        .as_ref()
+}
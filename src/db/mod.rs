@@ -5,10 +5,19 @@ pub mod types;
 
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use rocket::{fairing::AdHoc, Build, Rocket};
+use rocket_sync_db_pools::ConnectionPool;
 
 #[rocket_sync_db_pools::database("database")]
 pub struct Db(diesel::SqliteConnection);
 
+pub type DbPool = ConnectionPool<Db, diesel::SqliteConnection>;
+
+impl Db {
+    pub async fn from_pool(pool: &DbPool) -> Option<Self> {
+        pool.get().await.map(Self)
+    }
+}
+
 async fn run_migrations(rocket: Rocket<Build>) -> Rocket<Build> {
     const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
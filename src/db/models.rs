@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use diesel::prelude::*;
 use diesel_derive_enum::DbEnum;
 use serde::{Deserialize, Serialize};
@@ -5,7 +7,13 @@ use strum::EnumIter;
 use time::PrimitiveDateTime;
 use validator::{Validate, ValidationError};
 
-use crate::{core::conductor::Artifact as ArtifactInfo, utils::webcolor::parse_webcolor};
+use crate::{
+    core::{
+        conductor::{Artifact as ArtifactInfo, RunDockerResult},
+        proxy::Route,
+    },
+    utils::webcolor::parse_webcolor,
+};
 
 use super::{schema::*, types::Json};
 
@@ -18,6 +26,7 @@ use super::{schema::*, types::Json};
     PartialEq,
     Ord,
     PartialOrd,
+    Default,
     EnumIter,
     FromFormField,
     DbEnum,
@@ -25,17 +34,12 @@ use super::{schema::*, types::Json};
     Deserialize,
 )]
 pub enum UserRole {
+    #[default]
     Challenger,
     Administrator,
     Superuser,
 }
 
-impl Default for UserRole {
-    fn default() -> Self {
-        Self::Challenger
-    }
-}
-
 fn validate_username(username: &str) -> Result<(), ValidationError> {
     if !username.chars().all(|c| c.is_ascii_alphanumeric()) {
         return Err(ValidationError::new(
@@ -161,6 +165,12 @@ pub struct Challenge {
     pub dynamic: bool,
     pub public: bool,
     pub difficulty: Option<i32>,
+    #[validate(range(min = 0))]
+    pub pool: Option<i32>,
+    pub hardened: bool,
+    pub shared: bool,
+    #[validate(length(min = 1))]
+    pub slug: Option<String>,
 }
 
 #[derive(
@@ -189,6 +199,80 @@ pub struct Artifact {
     pub info: Json<Vec<ArtifactInfo>>,
     #[validate(length(min = 1))]
     pub path: String,
+    pub pooled: bool,
+    pub seed: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, DbEnum, Serialize, Deserialize)]
+pub enum BuildStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl BuildStatus {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    Insertable,
+    Queryable,
+    Associations,
+    Identifiable,
+    Selectable,
+    AsChangeset,
+    Validate,
+)]
+#[serde(crate = "rocket::serde")]
+#[diesel(belongs_to(User, foreign_key = user))]
+#[diesel(belongs_to(Challenge, foreign_key = challenge))]
+#[diesel(table_name = builds)]
+#[diesel(treat_none_as_null = true)]
+pub struct Build {
+    pub id: Option<i32>,
+    pub user: Option<i32>,
+    pub challenge: i32,
+    pub status: BuildStatus,
+    pub path: Option<String>,
+    pub error: Option<String>,
+    pub time: PrimitiveDateTime,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    Insertable,
+    Queryable,
+    Associations,
+    Identifiable,
+    Selectable,
+    AsChangeset,
+    Validate,
+)]
+#[serde(crate = "rocket::serde")]
+#[diesel(belongs_to(User, foreign_key = user))]
+#[diesel(belongs_to(Challenge, foreign_key = challenge))]
+#[diesel(table_name = instances)]
+#[diesel(treat_none_as_null = true)]
+pub struct Instance {
+    pub id: Option<i32>,
+    pub user: Option<i32>,
+    pub challenge: i32,
+    #[validate(range(min = 0))]
+    pub artifact: i32,
+    pub info: Json<RunDockerResult>,
+    pub start_at: PrimitiveDateTime,
+    pub stop_at: Option<PrimitiveDateTime>,
+    pub routes: Json<HashMap<String, Route>>,
 }
 
 #[derive(
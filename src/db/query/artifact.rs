@@ -25,7 +25,8 @@ pub async fn get_static_artifact(db: &Db, challenge: i32) -> QueryResult<Artifac
             .filter(
                 artifacts::user
                     .is_null()
-                    .and(artifacts::challenge.eq(challenge)),
+                    .and(artifacts::challenge.eq(challenge))
+                    .and(artifacts::pooled.eq(false)),
             )
             .first(conn)
     })
@@ -53,6 +54,39 @@ pub async fn get_artifact(db: &Db, challenge: i32, user: Option<i32>) -> QueryRe
     }
 }
 
+pub async fn count_pooled_artifacts(db: &Db, challenge: i32) -> QueryResult<i64> {
+    db.run(move |conn| {
+        artifacts::table
+            .filter(
+                artifacts::pooled
+                    .eq(true)
+                    .and(artifacts::challenge.eq(challenge)),
+            )
+            .count()
+            .get_result(conn)
+    })
+    .await
+}
+
+pub async fn claim_pooled_artifact(db: &Db, challenge: i32, user: i32) -> QueryResult<Artifact> {
+    db.run(move |conn| {
+        conn.immediate_transaction(|conn| {
+            let artifact: Artifact = artifacts::table
+                .filter(
+                    artifacts::pooled
+                        .eq(true)
+                        .and(artifacts::challenge.eq(challenge)),
+                )
+                .first(conn)?;
+
+            diesel::update(artifacts::table.filter(artifacts::id.eq(artifact.id)))
+                .set((artifacts::user.eq(user), artifacts::pooled.eq(false)))
+                .get_result(conn)
+        })
+    })
+    .await
+}
+
 pub async fn get_artifact_by_id(db: &Db, id: i32) -> QueryResult<Artifact> {
     db.run(move |conn| artifacts::table.filter(artifacts::id.eq(id)).first(conn))
         .await
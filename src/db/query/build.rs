@@ -0,0 +1,143 @@
+use diesel::prelude::*;
+
+use anyhow::Result as AnyResult;
+use diesel::QueryResult;
+use validator::Validate;
+
+use crate::db::{
+    models::{Build, BuildStatus},
+    schema::builds,
+    Db,
+};
+
+pub async fn add_build(db: &Db, build: Build) -> AnyResult<i32> {
+    build.validate()?;
+
+    Ok(db
+        .run(move |conn| {
+            diesel::insert_into(builds::table)
+                .values(&build)
+                .returning(builds::id)
+                .get_result(conn)
+        })
+        .await
+        .map(|id: Option<i32>| id.expect("returning guarantees id present"))?)
+}
+
+pub async fn add_build_if_idle(db: &Db, build: Build) -> AnyResult<Option<i32>> {
+    build.validate()?;
+
+    Ok(db
+        .run(move |conn| {
+            conn.immediate_transaction(|conn| {
+                let pending: i64 = builds::table
+                    .filter(
+                        builds::user
+                            .is(build.user)
+                            .and(builds::challenge.eq(build.challenge))
+                            .and(
+                                builds::status
+                                    .eq(BuildStatus::Queued)
+                                    .or(builds::status.eq(BuildStatus::Running)),
+                            ),
+                    )
+                    .count()
+                    .get_result(conn)?;
+
+                if pending > 0 {
+                    return Ok(None);
+                }
+
+                diesel::insert_into(builds::table)
+                    .values(&build)
+                    .returning(builds::id)
+                    .get_result(conn)
+                    .map(|id: Option<i32>| id)
+            })
+        })
+        .await?)
+}
+
+pub async fn update_build(db: &Db, build: Build) -> AnyResult<()> {
+    build.validate()?;
+
+    db.run(move |conn| {
+        diesel::update(builds::table.filter(builds::id.eq(build.id)))
+            .set(&build)
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_build(db: &Db, id: i32) -> QueryResult<Build> {
+    db.run(move |conn| builds::table.filter(builds::id.eq(id)).first(conn))
+        .await
+}
+
+pub async fn get_latest_build(db: &Db, challenge: i32, user: Option<i32>) -> QueryResult<Build> {
+    db.run(move |conn| {
+        builds::table
+            .filter(builds::user.is(user).and(builds::challenge.eq(challenge)))
+            .order(builds::id.desc())
+            .first(conn)
+    })
+    .await
+}
+
+pub async fn claim_queued_build(db: &Db) -> QueryResult<Build> {
+    db.run(move |conn| {
+        conn.immediate_transaction(|conn| {
+            let build: Build = builds::table
+                .filter(builds::status.eq(BuildStatus::Queued))
+                .order(builds::id.asc())
+                .first(conn)?;
+
+            diesel::update(builds::table.filter(builds::id.eq(build.id)))
+                .set(builds::status.eq(BuildStatus::Running))
+                .get_result(conn)
+        })
+    })
+    .await
+}
+
+pub async fn count_queued_builds_before(db: &Db, id: i32) -> QueryResult<i64> {
+    db.run(move |conn| {
+        builds::table
+            .filter(
+                builds::status
+                    .eq(BuildStatus::Queued)
+                    .and(builds::id.lt(id)),
+            )
+            .count()
+            .get_result(conn)
+    })
+    .await
+}
+
+pub async fn requeue_running_builds(db: &Db) -> QueryResult<()> {
+    db.run(move |conn| {
+        diesel::update(builds::table.filter(builds::status.eq(BuildStatus::Running)))
+            .set(builds::status.eq(BuildStatus::Queued))
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_builds(db: &Db) -> QueryResult<Vec<Build>> {
+    db.run(move |conn| builds::table.load(conn)).await
+}
+
+pub async fn delete_build(db: &Db, id: i32) -> QueryResult<()> {
+    db.run(move |conn| {
+        diesel::delete(builds::table)
+            .filter(builds::id.eq(id))
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
@@ -58,6 +58,15 @@ pub async fn list_problemset_challenges(db: &Db, id: i32) -> QueryResult<Vec<Cha
     .await
 }
 
+pub async fn get_challenge_by_slug(db: &Db, slug: String) -> QueryResult<Challenge> {
+    db.run(move |conn| {
+        challenges::table
+            .filter(challenges::slug.eq(slug))
+            .first(conn)
+    })
+    .await
+}
+
 pub async fn list_challenges(db: &Db) -> QueryResult<Vec<Challenge>> {
     db.run(move |conn| challenges::table.load(conn)).await
 }
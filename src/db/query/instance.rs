@@ -0,0 +1,77 @@
+use diesel::prelude::*;
+
+use anyhow::Result as AnyResult;
+use diesel::QueryResult;
+use time::PrimitiveDateTime;
+use validator::Validate;
+
+use crate::{
+    core::conductor::RunDockerResult,
+    db::{models::Instance, schema::instances, types::Json, Db},
+};
+
+pub async fn add_instance(db: &Db, instance: Instance) -> AnyResult<i32> {
+    instance.validate()?;
+
+    Ok(db
+        .run(move |conn| {
+            conn.immediate_transaction(|conn| {
+                diesel::delete(instances::table)
+                    .filter(
+                        instances::user
+                            .is(instance.user)
+                            .and(instances::challenge.eq(instance.challenge))
+                            .and(instances::artifact.eq(instance.artifact)),
+                    )
+                    .execute(conn)?;
+
+                diesel::insert_into(instances::table)
+                    .values(&instance)
+                    .returning(instances::id)
+                    .get_result(conn)
+            })
+        })
+        .await
+        .map(|id: Option<i32>| id.expect("returning guarantees id present"))?)
+}
+
+pub async fn update_instance_stop_at(
+    db: &Db,
+    id: i32,
+    stop_at: Option<PrimitiveDateTime>,
+) -> QueryResult<()> {
+    db.run(move |conn| {
+        diesel::update(instances::table.filter(instances::id.eq(id)))
+            .set(instances::stop_at.eq(stop_at))
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_instance_info(db: &Db, id: i32, info: RunDockerResult) -> QueryResult<()> {
+    db.run(move |conn| {
+        diesel::update(instances::table.filter(instances::id.eq(id)))
+            .set(instances::info.eq(Json(info)))
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_instances(db: &Db) -> QueryResult<Vec<Instance>> {
+    db.run(move |conn| instances::table.load(conn)).await
+}
+
+pub async fn delete_instance(db: &Db, id: i32) -> QueryResult<()> {
+    db.run(move |conn| {
+        diesel::delete(instances::table)
+            .filter(instances::id.eq(id))
+            .execute(conn)
+    })
+    .await?;
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 pub mod artifact;
+pub mod build;
 pub mod challenge;
 pub mod difficulty;
+pub mod instance;
 pub mod problemset;
 pub mod score;
 pub mod solved;
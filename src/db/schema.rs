@@ -6,6 +6,23 @@ diesel::table! {
         flag -> Text,
         info -> Text,
         path -> Text,
+        pooled -> Bool,
+        seed -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use crate::db::models::BuildStatusMapping;
+    use diesel::sql_types::{Nullable, Integer, Text, Timestamp};
+
+    builds (id) {
+        id -> Nullable<Integer>,
+        user -> Nullable<Integer>,
+        challenge -> Integer,
+        status -> BuildStatusMapping,
+        path -> Nullable<Text>,
+        error -> Nullable<Text>,
+        time -> Timestamp,
     }
 }
 
@@ -23,6 +40,10 @@ diesel::table! {
         dynamic -> Bool,
         public -> Bool,
         difficulty -> Nullable<Integer>,
+        pool -> Nullable<Integer>,
+        hardened -> Bool,
+        shared -> Bool,
+        slug -> Nullable<Text>,
     }
 }
 
@@ -34,6 +55,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    instances (id) {
+        id -> Nullable<Integer>,
+        user -> Nullable<Integer>,
+        challenge -> Integer,
+        artifact -> Integer,
+        info -> Text,
+        start_at -> Timestamp,
+        stop_at -> Nullable<Timestamp>,
+        routes -> Text,
+    }
+}
+
 diesel::table! {
     problemsets (id) {
         id -> Nullable<Integer>,
@@ -87,8 +121,12 @@ diesel::table! {
 
 diesel::joinable!(artifacts -> challenges (challenge));
 diesel::joinable!(artifacts -> users (user));
+diesel::joinable!(builds -> challenges (challenge));
+diesel::joinable!(builds -> users (user));
 diesel::joinable!(challenges -> difficulties (difficulty));
 diesel::joinable!(challenges -> problemsets (problemset));
+diesel::joinable!(instances -> challenges (challenge));
+diesel::joinable!(instances -> users (user));
 diesel::joinable!(scores -> challenges (challenge));
 diesel::joinable!(scores -> users (user));
 diesel::joinable!(solved -> scores (score));
@@ -98,8 +136,10 @@ diesel::joinable!(submissions -> users (user));
 
 diesel::allow_tables_to_appear_in_same_query!(
     artifacts,
+    builds,
     challenges,
     difficulties,
+    instances,
     problemsets,
     scores,
     solved,
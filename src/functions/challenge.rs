@@ -1,25 +1,37 @@
 use std::{
     cmp::Ordering,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs::File as StdFile,
-    io::{Cursor, Read},
+    io::{BufRead, BufReader, Cursor, Read, Write},
     net::SocketAddr,
-    path::PathBuf,
-    sync::LazyLock,
+    path::{Component, Path, PathBuf},
+    pin::pin,
+    sync::{Arc, LazyLock, OnceLock},
     time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, bail, Result};
 use either::Either;
-use flate2::{write::GzEncoder, Compression};
-use futures_util::FutureExt;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures_util::{future, FutureExt, SinkExt, StreamExt};
 use koto::prelude::*;
-use moka::{future::Cache, notification::ListenerFuture};
+use moka::{
+    future::Cache,
+    notification::{ListenerFuture, RemovalCause},
+    ops::compute::{CompResult, Op},
+    Expiry,
+};
 use rocket::{fairing::AdHoc, fs::TempFile as RocketTempFile};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::PrimitiveDateTime;
 use tokio::{
     fs::{self, File},
-    sync::{Mutex, RwLock},
+    io::AsyncWriteExt,
+    sync::{Mutex, Notify, RwLock},
 };
+use tokio_tungstenite::tungstenite::Message;
+use validator::Validate;
 
 #[cfg(feature = "activity")]
 use crate::{
@@ -27,50 +39,176 @@ use crate::{
     db::query::{
         problemset::get_problemset,
         solved::{count_challenge_effective_solved, get_solved},
-        user::get_user,
     },
 };
 
 use crate::{
     configs::challenge::{MappedAddr, CONFIG},
-    core::conductor::{self, Artifact, BuildInfo, RunDockerResult},
+    core::{
+        conductor::{self, Artifact, BuildInfo, BuildVars, DockerStats, RunDockerResult, Terminal},
+        proxy::{self, Route, RouteKind},
+    },
     db::{
-        models::{Artifact as ArtifactEntry, Challenge, Score, Solved, Submission},
+        models::{
+            Artifact as ArtifactEntry, Build, BuildStatus, Challenge, Instance as InstanceEntry,
+            Score, Solved, Submission,
+        },
         query::{
-            artifact::{delete_artifact, get_artifact, list_challenge_artifacts, update_artifact},
-            challenge::{delete_challenge, get_challenge, list_challenges, update_challenge},
+            artifact::{
+                claim_pooled_artifact, count_pooled_artifacts, delete_artifact, get_artifact,
+                list_artifacts, list_challenge_artifacts, update_artifact,
+            },
+            build::{
+                add_build, add_build_if_idle, claim_queued_build, count_queued_builds_before,
+                delete_build, get_build, get_latest_build, requeue_running_builds, update_build,
+            },
+            challenge::{
+                add_challenge, delete_challenge, get_challenge, get_challenge_by_slug,
+                list_challenges, update_challenge,
+            },
+            difficulty::list_difficulties,
+            instance::{
+                add_instance, delete_instance, list_instances, update_instance_info,
+                update_instance_stop_at,
+            },
+            problemset::list_problemsets,
             score::add_score,
             solved::{list_challenge_effective_solved_with_submission, update_solved},
             submission::add_submission,
+            user::get_user,
         },
-        Db,
+        Db, DbPool,
+    },
+    utils::{
+        dynfmt, fsext, query::QueryResultExt, responder::NamedFile, script::KotoScript,
+        websocket::WebSocketConnection,
     },
-    utils::{dynfmt, responder::NamedFile, script::KotoScript},
 };
 
 use super::event::{cmp_period, primitive_now};
 
 #[derive(Clone, Debug)]
 struct DockerInstance {
+    id: i32,
     info: RunDockerResult,
+    routes: HashMap<String, Route>,
+    start_at: Instant,
     stop_at: Option<Instant>,
+    closed: Arc<Notify>,
+}
+
+impl DockerInstance {
+    fn extended_stop_at(&self, privileged: bool) -> Option<Instant> {
+        let stop_at = self.stop_at?;
+
+        let extension = match privileged {
+            true => CONFIG.docker.extension.or(CONFIG.docker.expiry)?,
+            false => CONFIG.docker.extension?,
+        };
+
+        let mut extended = stop_at.checked_add(extension)?;
+
+        if let Some(max) = CONFIG
+            .docker
+            .max_lifetime
+            .filter(|_| !privileged)
+            .and_then(|lifetime| self.start_at.checked_add(lifetime))
+        {
+            extended = extended.min(max);
+        }
+
+        (extended > stop_at).then_some(extended)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DockerActivity {
+    network: Option<u64>,
+    last: Instant,
+}
+
+struct DockerInstanceExpiry;
+
+impl Expiry<ArtifactIndex, DockerInstance> for DockerInstanceExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &ArtifactIndex,
+        value: &DockerInstance,
+        created_at: Instant,
+    ) -> Option<Duration> {
+        value
+            .stop_at
+            .map(|stop_at| stop_at.saturating_duration_since(created_at))
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &ArtifactIndex,
+        value: &DockerInstance,
+        updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        value
+            .stop_at
+            .map(|stop_at| stop_at.saturating_duration_since(updated_at))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerInstanceUsage {
+    pub user: usize,
+    pub user_limit: Option<usize>,
+    pub total: usize,
+    pub total_limit: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DockerInstanceEntry {
+    pub user: Option<i32>,
+    pub challenge: i32,
+    pub artifact: usize,
+    pub info: RunDockerResult,
+    pub age: Duration,
+    pub expiry: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
 pub struct DockerInstanceInfo {
     pub expiry: Option<Duration>,
+    pub extendable: bool,
     pub ports: Vec<(String, Vec<SocketAddr>)>,
+    pub urls: Vec<(String, String)>,
+    pub connections: Vec<(String, String)>,
+}
+
+type ArtifactIndex = (Option<i32>, i32, usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum DockerAction {
+    Restart,
+    Reset,
 }
 
-type ArtifactIndex = (i32, i32, usize);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 #[allow(clippy::type_complexity)]
 static BUILDING: LazyLock<RwLock<HashSet<(Option<i32>, i32)>>> =
     LazyLock::new(|| RwLock::new(HashSet::new()));
 
+static BUILD_NOTIFY: LazyLock<Notify> = LazyLock::new(Notify::new);
+
 static DOCKER_PREPARING: LazyLock<RwLock<HashSet<ArtifactIndex>>> =
     LazyLock::new(|| RwLock::new(HashSet::new()));
 
+static DOCKER_COOLDOWNS: LazyLock<Mutex<HashMap<(ArtifactIndex, DockerAction), Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static DOCKER_ACTIVITIES: LazyLock<Mutex<HashMap<ArtifactIndex, DockerActivity>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static DOCKER_REAPED: LazyLock<RwLock<HashSet<ArtifactIndex>>> =
+    LazyLock::new(|| RwLock::new(HashSet::new()));
+
 static DYNPOINTS_INSTANCE: LazyLock<Option<Mutex<KotoScript>>> = LazyLock::new(|| {
     if let Some(path) = &CONFIG.dynpoints {
         let mut buf = Vec::new();
@@ -86,39 +224,84 @@ static DYNPOINTS_INSTANCE: LazyLock<Option<Mutex<KotoScript>>> = LazyLock::new(|
     None
 });
 
+static INSTANCE_DB_POOL: OnceLock<DbPool> = OnceLock::new();
+
 static DOCKER_INSTANCES: LazyLock<Cache<ArtifactIndex, DockerInstance>> = LazyLock::new(|| {
-    let eviction_listener = move |_, v: DockerInstance, _| -> ListenerFuture {
+    let eviction_listener = move |k: Arc<_>, v: DockerInstance, cause| -> ListenerFuture {
         async move {
-            if let Err(e) = conductor::stop_docker(&v.info.id).await {
+            // the instance is still running after its entry being replaced on extension.
+            if cause == RemovalCause::Replaced {
+                return;
+            }
+
+            v.closed.notify_waiters();
+
+            DOCKER_COOLDOWNS
+                .lock()
+                .await
+                .retain(|(idx, _), _| idx != &*k);
+
+            for route in v.routes.values() {
+                proxy::remove_route(route).await;
+            }
+
+            if let Err(e) = conductor::stop_docker(&v.info).await {
                 log::error!(target: "challenge", "failed to stop docker on eviction: {e:?}")
             }
+
+            if let Some(pool) = INSTANCE_DB_POOL.get() {
+                if let Some(db) = Db::from_pool(pool).await {
+                    if let Err(e) = delete_instance(&db, v.id).await {
+                        log::error!(target: "challenge", "failed to delete instance on eviction: {e:?}")
+                    }
+                }
+            }
         }
         .boxed()
     };
 
-    let mut builder = Cache::builder().async_eviction_listener(eviction_listener);
-
-    if let Some(expiry) = CONFIG.docker.expiry {
-        builder = builder.time_to_live(expiry)
-    }
-
-    builder.build()
+    Cache::builder()
+        .async_eviction_listener(eviction_listener)
+        .expire_after(DockerInstanceExpiry)
+        .build()
 });
 
-pub async fn uninitialize() {
-    stop_all_active_sessions().await;
-}
-
 pub fn stage() -> AdHoc {
     AdHoc::on_ignite("Function - Challenge", |rocket| async {
-        rocket.attach(AdHoc::on_shutdown(
-            "Uninitialize Challenge Function",
-            |_| {
-                Box::pin(async move {
-                    uninitialize().await;
-                })
-            },
-        ))
+        rocket.attach(AdHoc::on_liftoff("Start Challenge Services", |rocket| {
+            Box::pin(async move {
+                let pool = Db::pool(rocket).expect("database pool").clone();
+
+                // instances are kept running across restarts and re-adopted here,
+                // before any build could create new containers.
+                restore_instances(&pool).await;
+                start_build_workers(pool).await;
+
+                if let Some(timeout) = CONFIG.docker.idle_timeout {
+                    tokio::spawn(idle_reaper(timeout));
+                }
+
+                if let Some(config) = &CONFIG.proxy {
+                    tokio::spawn(async move {
+                        let domain = config.domain.clone();
+
+                        if let Err(e) = proxy::serve_http(config.listen, domain).await {
+                            log::error!(target: "challenge", "http proxy stopped: {e:?}")
+                        }
+                    });
+                }
+
+                if let Some(config) = &CONFIG.gateway {
+                    tokio::spawn(async move {
+                        let domain = config.domain.clone();
+
+                        if let Err(e) = proxy::serve_tcp(config.listen, domain).await {
+                            log::error!(target: "challenge", "tcp gateway stopped: {e:?}")
+                        }
+                    });
+                }
+            })
+        }))
     })
 }
 
@@ -127,6 +310,24 @@ fn generate_random_flag(fmt: &str) -> Result<String> {
     dynfmt::format(fmt, &[&inner])
 }
 
+fn generate_seed(challenge: &Challenge, user: Option<i32>) -> String {
+    // pooled artifacts are handed to different users, so each one gets its own seed.
+    let user = match user {
+        Some(user) => user.to_string(),
+        None if challenge.dynamic => uuid::Uuid::new_v4().as_simple().to_string(),
+        None => String::new(),
+    };
+
+    format!(
+        "{:x}",
+        Sha256::new()
+            .chain_update(challenge.flag.as_bytes())
+            .chain_update(challenge.id.unwrap_or_default().to_be_bytes())
+            .chain_update(user.as_bytes())
+            .finalize()
+    )
+}
+
 async fn calculate_points(initial: f64, solved: i64) -> Result<f64> {
     if let Some(lock) = &*DYNPOINTS_INSTANCE {
         let mut script = lock.lock().await;
@@ -314,238 +515,1825 @@ pub async fn save_files(
     result
 }
 
-pub async fn load_build_info(db: &Db, id: i32) -> Result<BuildInfo> {
-    let challenge = get_challenge(db, id).await?;
+fn default_true() -> bool {
+    true
+}
 
-    let path = CONFIG.challenge_root.join(&challenge.path);
-    let source = path.join("source");
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ChallengeManifest {
+    #[serde(default)]
+    #[validate(length(min = 1))]
+    pub slug: Option<String>,
+    #[validate(length(min = 1))]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[validate(range(min = 1.0))]
+    pub points: f64,
+    #[serde(default)]
+    pub flag: Option<String>,
+    #[serde(default)]
+    pub dynamic: bool,
+    #[serde(default = "default_true")]
+    pub public: bool,
+    #[serde(default)]
+    pub problemset: Option<String>,
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub pool: Option<i32>,
+    #[serde(default = "default_true")]
+    pub hardened: bool,
+    #[serde(default)]
+    pub shared: bool,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
 
-    conductor::load_build_info(source).await
+pub struct ImportedChallenge {
+    pub id: i32,
+    pub created: bool,
+    pub dynamic: bool,
+    pub has_source: bool,
 }
 
-pub async fn clear_artifact(artifact: &ArtifactEntry) {
-    stop_active_sessions(artifact.user, artifact.challenge).await;
+pub async fn load_manifest<P: AsRef<Path>>(path: P) -> Result<ChallengeManifest> {
+    let yaml = fs::read_to_string(path.as_ref().join("challenge.yml")).await?;
 
-    let path = CONFIG.artifact_root.join(&artifact.path);
-    conductor::clear_artifact(&path, &artifact.info).await
-}
+    let manifest: ChallengeManifest =
+        tokio::task::spawn_blocking(move || serde_yml::from_str(&yaml)).await??;
+    manifest.validate()?;
 
-pub async fn remove_challenge(db: &Db, id: i32) -> Result<()> {
-    let artifacts = list_challenge_artifacts(db, id).await?;
+    if manifest.dynamic && manifest.shared {
+        bail!("dynamic challenge cannot use a shared instance.");
+    }
 
-    for artifact in artifacts {
-        clear_artifact(&artifact).await;
+    if !manifest.dynamic && manifest.flag.as_ref().is_none_or(|flag| flag.is_empty()) {
+        bail!("flag of static challenge should not be empty.");
     }
 
-    let challenge = get_challenge(db, id).await?;
+    Ok(manifest)
+}
 
-    let path = CONFIG.challenge_root.join(&challenge.path);
-    fs::remove_dir_all(&path).await?;
+fn unpack_package<R: Read>(archive: R, root: &Path) -> Result<()> {
+    let mut archive = BufReader::new(archive);
+    let gzip = archive.fill_buf()?.starts_with(&[0x1f, 0x8b]);
 
-    delete_challenge(db, id).await?;
+    let archive = match gzip {
+        true => Either::Left(GzDecoder::new(archive)),
+        false => Either::Right(archive),
+    };
+
+    let mut tar = tar::Archive::new(archive);
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("unsafe path '{}' detected.", path.display());
+        }
+
+        let path = root.join(&path);
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => std::fs::create_dir_all(&path)?,
+            tar::EntryType::Regular => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                entry.unpack(&path)?;
+            }
+            kind => bail!(
+                "unsupported entry type {kind:?} of '{}', only regular files and directories are allowed.",
+                path.display()
+            ),
+        }
+    }
 
     Ok(())
 }
 
-pub async fn build_challenge(db: &Db, user: Option<i32>, challenge: i32) -> Result<()> {
-    if !BUILDING.write().await.insert((user, challenge)) {
-        bail!("challenge {challenge} build task for user {user:?} has already started.");
-    }
+async fn copy_package(
+    package: &Path,
+    manifest: &ChallengeManifest,
+) -> Result<(String, Vec<String>)> {
+    let name = uuid::Uuid::new_v4().hyphenated().to_string();
+    let path = CONFIG.challenge_root.join(&name);
 
     let result = async {
-        let entry = get_challenge(db, challenge).await?;
+        let attachment_dir = path.join("attachment");
 
-        if entry.dynamic && user.is_none() {
-            bail!("user id is needed for dynamic challenge.");
-        }
+        let mut saved_attachments = Vec::new();
 
-        if !entry.dynamic && user.is_some() {
-            bail!("static challenge should not have a user id assigned.");
-        }
+        fs::create_dir_all(&attachment_dir).await?;
 
-        let old_artifact = get_artifact(db, challenge, user).await;
+        for attachment in &manifest.attachments {
+            let relative = Path::new(attachment);
 
-        let name = uuid::Uuid::new_v4().hyphenated().to_string();
-        let target = CONFIG.artifact_root.join(&name);
+            if !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                bail!("unsafe attachment path '{attachment}' detected.");
+            }
 
-        let path = CONFIG.challenge_root.join(&entry.path);
-        let source = path.join("source");
+            let name = relative
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("invalid filename."))?;
 
-        let flag = entry.flag.clone();
+            if saved_attachments.iter().any(|saved| saved == name) {
+                bail!("duplicated attachment name '{name}'.");
+            }
 
-        let flag = match entry.dynamic {
-            true => generate_random_flag(&flag)?,
-            false => flag,
-        };
+            let source = package.join(relative);
 
-        let result = conductor::build(&source, &target, true, &flag).await?;
+            if !fs::symlink_metadata(&source).await?.is_file() {
+                bail!("attachment '{attachment}' is not a regular file.");
+            }
 
-        let artifact = ArtifactEntry {
-            id: None,
-            user,
-            challenge,
-            flag,
-            path: name,
-            info: result.artifacts.into(),
-        };
+            fs::copy(&source, attachment_dir.join(name)).await?;
+            saved_attachments.push(name.to_string());
+        }
 
-        update_artifact(db, artifact).await?;
+        let source = package.join("source");
 
-        if let Ok(artifact) = old_artifact {
-            clear_artifact(&artifact).await;
-            _ = delete_artifact(db, artifact.id.unwrap()).await;
+        if source.is_dir() {
+            let source_dir = path.join("source");
+
+            let copy_options = fsext::CopyOptions::new().follow_symlink(false);
+            fsext::copy_dir(&source, &source_dir, &copy_options).await?;
+
+            _ = conductor::load_build_info(&source_dir).await?;
+        } else if manifest.dynamic {
+            bail!("dynamic challenge requires a source.");
         }
 
-        Ok(())
+        Ok((name, saved_attachments))
     }
     .await;
 
-    BUILDING.write().await.remove(&(user, challenge));
+    if result.is_err() {
+        _ = fs::remove_dir_all(&path).await;
+    }
 
     result
 }
 
-pub async fn is_challenge_building(user: Option<i32>, challenge: i32) -> bool {
-    BUILDING.read().await.contains(&(user, challenge))
+async fn resolve_manifest(
+    db: &Db,
+    manifest: &ChallengeManifest,
+) -> Result<(Option<i32>, Option<i32>)> {
+    let problemset = match &manifest.problemset {
+        Some(name) => Some(
+            list_problemsets(db)
+                .await?
+                .into_iter()
+                .find(|problemset| &problemset.name == name)
+                .and_then(|problemset| problemset.id)
+                .ok_or_else(|| anyhow!("problemset '{name}' not found."))?,
+        ),
+        None => None,
+    };
+
+    let difficulty = match &manifest.difficulty {
+        Some(name) => Some(
+            list_difficulties(db)
+                .await?
+                .into_iter()
+                .find(|difficulty| &difficulty.name == name)
+                .and_then(|difficulty| difficulty.id)
+                .ok_or_else(|| anyhow!("difficulty '{name}' not found."))?,
+        ),
+        None => None,
+    };
+
+    Ok((problemset, difficulty))
 }
 
-pub async fn is_docker_running(user: i32, challenge: i32, artifact: usize) -> bool {
-    DOCKER_INSTANCES.contains_key(&(user, challenge, artifact))
+async fn find_manifest_challenge(
+    db: &Db,
+    manifest: &ChallengeManifest,
+) -> Result<Option<Challenge>> {
+    if let Some(slug) = &manifest.slug {
+        if let Some(challenge) = get_challenge_by_slug(db, slug.clone()).await.some()? {
+            return Ok(Some(challenge));
+        }
+    }
+
+    // challenges added without a slug are adopted by name.
+    let mut existing = list_challenges(db).await?.into_iter().filter(|challenge| {
+        challenge.name == manifest.name && (manifest.slug.is_none() || challenge.slug.is_none())
+    });
+
+    let challenge = existing.next();
+
+    if existing.next().is_some() {
+        bail!("multiple challenges named '{}' found.", manifest.name);
+    }
+
+    Ok(challenge)
 }
 
-pub async fn run_docker(db: &Db, user: i32, challenge: i32, artifact: usize) -> Result<()> {
-    if !DOCKER_PREPARING
-        .write()
-        .await
-        .insert((user, challenge, artifact))
-    {
-        bail!("docker {artifact} of challenge {challenge} for user {user} is preparing.");
+fn manifest_flag(manifest: &ChallengeManifest) -> String {
+    match &manifest.flag {
+        Some(flag) if !flag.is_empty() => flag.clone(),
+        _ => "flag{{{}}}".to_string(),
     }
+}
 
-    let result = async {
-        if is_docker_running(user, challenge, artifact).await {
-            bail!("a docker instance is already running.");
+async fn import_manifest(
+    db: &Db,
+    package: &Path,
+    manifest: ChallengeManifest,
+    old_challenge: Option<Challenge>,
+) -> Result<ImportedChallenge> {
+    if let Some(challenge) = &old_challenge {
+        if challenge.dynamic != manifest.dynamic {
+            bail!(
+                "dynamic of challenge '{}' cannot be changed.",
+                manifest.name
+            );
         }
+    }
 
-        let entry = get_challenge(db, challenge).await?;
-        let entry = get_artifact(db, challenge, entry.dynamic.then_some(user)).await?;
+    let (problemset, difficulty) = resolve_manifest(db, &manifest).await?;
+
+    let (path, attachments) = copy_package(package, &manifest).await?;
+    let has_source = CONFIG.challenge_root.join(&path).join("source").is_dir();
+
+    let challenge = Challenge {
+        id: old_challenge.as_ref().and_then(|challenge| challenge.id),
+        name: manifest.name.clone(),
+        description: manifest.description.clone(),
+        path: path.clone(),
+        attachments: attachments.into(),
+        problemset,
+        dynamic: manifest.dynamic,
+        flag: manifest_flag(&manifest),
+        initial: manifest.points,
+        points: old_challenge
+            .as_ref()
+            .map(|challenge| challenge.points)
+            .unwrap_or(manifest.points),
+        public: manifest.public,
+        difficulty,
+        pool: manifest.pool,
+        hardened: manifest.hardened,
+        shared: manifest.shared,
+        slug: manifest.slug.clone().or_else(|| {
+            old_challenge
+                .as_ref()
+                .and_then(|challenge| challenge.slug.clone())
+        }),
+    };
 
-        let info = entry
-            .info
-            .0
-            .into_iter()
-            .nth(artifact)
-            .ok_or_else(|| anyhow!("artifact not found."))?;
+    let result = match &old_challenge {
+        Some(old_challenge) => update_challenge(db, challenge)
+            .await
+            .map(|_| old_challenge.id.unwrap()),
+        None => add_challenge(db, challenge).await,
+    };
 
-        if let Artifact::Docker(docker) = &info {
-            let info = conductor::run_docker(docker, &CONFIG.docker.options).await?;
+    let id = match result {
+        Ok(id) => id,
+        Err(err) => {
+            _ = fs::remove_dir_all(CONFIG.challenge_root.join(&path)).await;
+            return Err(err);
+        }
+    };
 
-            let stop_at = CONFIG
-                .docker
-                .expiry
-                .and_then(|expiry| Instant::now().checked_add(expiry));
+    if let Some(old_challenge) = &old_challenge {
+        _ = fs::remove_dir_all(CONFIG.challenge_root.join(&old_challenge.path)).await;
 
-            let instance = DockerInstance { info, stop_at };
+        if old_challenge.shared != manifest.shared {
+            stop_active_sessions(None, id).await;
+        }
 
-            DOCKER_INSTANCES
-                .insert((user, challenge, artifact), instance)
-                .await;
+        if old_challenge.initial != manifest.points {
+            recalculate_challenge_points(db, id).await?;
+        }
+    }
 
-            DOCKER_PREPARING
-                .write()
-                .await
-                .remove(&(user, challenge, artifact));
+    Ok(ImportedChallenge {
+        id,
+        created: old_challenge.is_none(),
+        dynamic: manifest.dynamic,
+        has_source,
+    })
+}
 
-            return Ok(());
-        }
+pub async fn import_challenge(db: &Db, package: &Path) -> Result<ImportedChallenge> {
+    let manifest = load_manifest(package).await?;
+    let old_challenge = find_manifest_challenge(db, &manifest).await?;
+
+    import_manifest(db, package, manifest, old_challenge).await
+}
+
+pub async fn import_challenge_package(
+    db: &Db,
+    package: RocketTempFile<'_>,
+) -> Result<ImportedChallenge> {
+    let package = match package {
+        RocketTempFile::File { path, .. } => Either::Left(StdFile::open(path)?),
+        RocketTempFile::Buffered { content } => Either::Right(content),
+    };
+
+    let staging = CONFIG
+        .challenge_root
+        .join(format!("import-{}", uuid::Uuid::new_v4().as_simple()));
 
-        bail!("unexpected artifact type got.");
+    let result = async {
+        std::fs::create_dir_all(&staging)?;
+        unpack_package(package, &staging)?;
+
+        import_challenge(db, &staging).await
     }
     .await;
 
-    DOCKER_PREPARING
-        .write()
-        .await
-        .remove(&(user, challenge, artifact));
+    if staging.exists() {
+        _ = fs::remove_dir_all(&staging).await;
+    }
 
     result
 }
 
-pub async fn stop_docker(user: i32, challenge: i32, artifact: usize) {
-    DOCKER_INSTANCES
-        .invalidate(&(user, challenge, artifact))
-        .await;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SyncAction {
+    Create,
+    Update,
+    Unchanged,
+    Remove,
+    Error,
 }
 
-pub async fn stop_dockers(user: Option<i32>, challenge: i32) {
-    for (idx, _) in DOCKER_INSTANCES.iter() {
-        if user.map(|x| idx.0 == x).unwrap_or(true) && idx.1 == challenge {
-            DOCKER_INSTANCES.invalidate(&idx).await;
-        }
-    }
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncEntry {
+    pub slug: String,
+    pub name: String,
+    pub action: SyncAction,
+    pub changes: Vec<&'static str>,
+    pub rebuild: bool,
+    pub error: Option<String>,
 }
 
-pub async fn stop_all_dockers() {
-    for (idx, _) in DOCKER_INSTANCES.iter() {
-        DOCKER_INSTANCES.invalidate(&idx).await;
-    }
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub dry_run: bool,
+    pub entries: Vec<SyncEntry>,
 }
 
-pub async fn stop_active_sessions(user: Option<i32>, challenge: i32) {
-    stop_dockers(user, challenge).await;
-}
+async fn hash_source(path: PathBuf) -> Result<Option<Vec<u8>>> {
+    tokio::task::spawn_blocking(move || {
+        if !path.is_dir() {
+            return Ok(None);
+        }
 
-pub async fn stop_all_active_sessions() {
-    stop_all_dockers().await;
+        let mut hasher = Sha256::new();
+        conductor::hash_dir(&mut hasher, &path, Path::new(""))?;
+
+        Ok(Some(hasher.finalize().to_vec()))
+    })
+    .await?
 }
 
-fn mapped_addr(addr: &MappedAddr, port: u16) -> SocketAddr {
-    let port = addr
-        .ports
-        .as_ref()
-        .map(|mapped| {
-            let ports = CONFIG
-                .docker
-                .options
-                .ports
-                .as_ref()
-                .expect("'ports' should be set here.");
+async fn hash_attachments(files: Vec<(String, PathBuf)>) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
 
-            port - ports.start() + mapped.start()
-        })
-        .unwrap_or(port);
+        for (name, path) in files {
+            let content = std::fs::read(path)?;
+            hasher.update(name.as_bytes());
+            hasher.update(content.len().to_be_bytes());
+            hasher.update(content);
+        }
 
-    SocketAddr::new(addr.addr, port)
+        Ok(hasher.finalize().to_vec())
+    })
+    .await?
 }
 
-pub async fn get_docker_instance_info(
-    user: i32,
-    challenge: i32,
-    artifact: usize,
-) -> Result<DockerInstanceInfo> {
-    let instance = DOCKER_INSTANCES
-        .get(&(user, challenge, artifact))
-        .await
-        .ok_or_else(|| anyhow!("docker instance not found."))?;
+async fn diff_manifest(
+    db: &Db,
+    package: &Path,
+    manifest: &ChallengeManifest,
+    challenge: &Challenge,
+) -> Result<(Vec<&'static str>, bool)> {
+    if challenge.dynamic != manifest.dynamic {
+        bail!(
+            "dynamic of challenge '{}' cannot be changed.",
+            manifest.name
+        );
+    }
 
-    let expiry = instance.stop_at.map(|stop_at| {
-        let now = Instant::now();
-        if now < stop_at {
-            stop_at.duration_since(now)
-        } else {
-            Duration::ZERO
+    let (problemset, difficulty) = resolve_manifest(db, manifest).await?;
+
+    let mut changes = Vec::new();
+
+    let fields = [
+        ("slug", challenge.slug != manifest.slug),
+        ("name", challenge.name != manifest.name),
+        ("description", challenge.description != manifest.description),
+        ("flag", challenge.flag != manifest_flag(manifest)),
+        ("points", challenge.initial != manifest.points),
+        ("public", challenge.public != manifest.public),
+        ("problemset", challenge.problemset != problemset),
+        ("difficulty", challenge.difficulty != difficulty),
+        ("pool", challenge.pool != manifest.pool),
+        ("hardened", challenge.hardened != manifest.hardened),
+        ("shared", challenge.shared != manifest.shared),
+    ];
+
+    for (field, changed) in fields {
+        if changed {
+            changes.push(field);
         }
-    });
+    }
 
-    let ports: Vec<_> = instance
-        .info
-        .ports
-        .clone()
-        .into_iter()
-        .map(|(exposed, port)| {
-            (
-                exposed,
-                CONFIG
+    let path = CONFIG.challenge_root.join(&challenge.path);
+
+    let old_attachments = challenge
+        .attachments
+        .iter()
+        .map(|name| (name.clone(), path.join("attachment").join(name)))
+        .collect();
+
+    let new_attachments = manifest
+        .attachments
+        .iter()
+        .map(|attachment| {
+            let name = Path::new(attachment)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            (name, package.join(attachment))
+        })
+        .collect();
+
+    if hash_attachments(old_attachments).await.ok()
+        != Some(hash_attachments(new_attachments).await?)
+    {
+        changes.push("attachments");
+    }
+
+    let source = package.join("source");
+    let source_changed =
+        hash_source(path.join("source")).await? != hash_source(source.clone()).await?;
+
+    if source_changed {
+        changes.push("source");
+    }
+
+    // flags are baked into the artifacts at build time.
+    let rebuild = source_changed || (changes.contains(&"flag") && source.is_dir());
+
+    Ok((changes, rebuild))
+}
+
+async fn sync_package(
+    db: &Db,
+    package: &Path,
+    manifest: ChallengeManifest,
+    dry_run: bool,
+) -> Result<SyncEntry> {
+    let source = package.join("source");
+
+    if source.is_dir() {
+        _ = conductor::load_build_info(&source).await?;
+    } else if manifest.dynamic {
+        bail!("dynamic challenge requires a source.");
+    }
+
+    let challenge = find_manifest_challenge(db, &manifest).await?;
+
+    let (action, changes, rebuild) = match &challenge {
+        Some(challenge) => {
+            let (changes, rebuild) = diff_manifest(db, package, &manifest, challenge).await?;
+
+            match changes.is_empty() {
+                true => (SyncAction::Unchanged, changes, false),
+                false => (SyncAction::Update, changes, rebuild),
+            }
+        }
+        None => {
+            resolve_manifest(db, &manifest).await?;
+            (SyncAction::Create, Vec::new(), source.is_dir())
+        }
+    };
+
+    let entry = SyncEntry {
+        slug: manifest.slug.clone().unwrap_or_default(),
+        name: manifest.name.clone(),
+        action,
+        changes,
+        rebuild,
+        error: None,
+    };
+
+    if dry_run || action == SyncAction::Unchanged {
+        return Ok(entry);
+    }
+
+    let imported = import_manifest(db, package, manifest, challenge).await?;
+
+    if imported.dynamic {
+        if entry.rebuild {
+            purge_pooled_artifacts(db, imported.id).await?;
+        }
+
+        refill_pool(db, imported.id).await?;
+    } else if imported.has_source && entry.rebuild {
+        build_challenge(db, None, imported.id).await?;
+    }
+
+    Ok(entry)
+}
+
+pub async fn sync_challenges(db: &Db, dry_run: bool) -> Result<SyncReport> {
+    let root = CONFIG
+        .sync_root
+        .as_ref()
+        .ok_or_else(|| anyhow!("sync root is not configured."))?;
+
+    let mut packages = Vec::new();
+    let mut read_dir = fs::read_dir(root).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+
+        if path.join("challenge.yml").is_file() {
+            packages.push((entry.file_name().to_string_lossy().to_string(), path));
+        }
+    }
+
+    packages.sort();
+
+    let mut entries = Vec::new();
+    let mut slugs = HashSet::new();
+
+    for (name, package) in packages {
+        let result = async {
+            let mut manifest = load_manifest(&package).await?;
+            let slug = manifest.slug.get_or_insert(name.clone()).clone();
+
+            if !slugs.insert(slug.clone()) {
+                bail!("duplicated slug '{slug}'.");
+            }
+
+            sync_package(db, &package, manifest, dry_run).await
+        }
+        .await;
+
+        let entry = result.unwrap_or_else(|err| {
+            slugs.insert(name.clone());
+
+            SyncEntry {
+                slug: name.clone(),
+                name,
+                action: SyncAction::Error,
+                changes: Vec::new(),
+                rebuild: false,
+                error: Some(err.to_string()),
+            }
+        });
+
+        entries.push(entry);
+    }
+
+    // challenges are never deleted by a sync, removals are only reported.
+    for challenge in list_challenges(db).await? {
+        if let Some(slug) = challenge.slug {
+            if !slugs.contains(&slug) {
+                entries.push(SyncEntry {
+                    slug,
+                    name: challenge.name,
+                    action: SyncAction::Remove,
+                    changes: Vec::new(),
+                    rebuild: false,
+                    error: None,
+                });
+            }
+        }
+    }
+
+    Ok(SyncReport { dry_run, entries })
+}
+
+pub async fn load_build_info(db: &Db, id: i32) -> Result<BuildInfo> {
+    let challenge = get_challenge(db, id).await?;
+
+    let path = CONFIG.challenge_root.join(&challenge.path);
+    let source = path.join("source");
+
+    conductor::load_build_info(source).await
+}
+
+fn build_log_path(name: &str) -> PathBuf {
+    CONFIG.artifact_root.join(name).with_extension("log")
+}
+
+pub async fn clear_artifact(artifact: &ArtifactEntry) {
+    stop_active_sessions(artifact.user, artifact.challenge).await;
+
+    _ = fs::remove_file(build_log_path(&artifact.path)).await;
+
+    let path = CONFIG.artifact_root.join(&artifact.path);
+    conductor::clear_artifact(&path, &artifact.info).await
+}
+
+// pooled artifacts are not assigned to anyone yet, so no session is attached to them.
+async fn purge_pooled_artifacts(db: &Db, challenge: i32) -> Result<()> {
+    for artifact in list_challenge_artifacts(db, challenge).await? {
+        if !artifact.pooled {
+            continue;
+        }
+
+        _ = fs::remove_file(build_log_path(&artifact.path)).await;
+
+        let path = CONFIG.artifact_root.join(&artifact.path);
+        conductor::clear_artifact(&path, &artifact.info).await;
+
+        delete_artifact(db, artifact.id.unwrap()).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn remove_challenge(db: &Db, id: i32) -> Result<()> {
+    let artifacts = list_challenge_artifacts(db, id).await?;
+
+    for artifact in artifacts {
+        clear_artifact(&artifact).await;
+    }
+
+    let challenge = get_challenge(db, id).await?;
+
+    let path = CONFIG.challenge_root.join(&challenge.path);
+    fs::remove_dir_all(&path).await?;
+
+    delete_challenge(db, id).await?;
+
+    Ok(())
+}
+
+async fn execute_build(db: &Db, user: Option<i32>, challenge: i32, name: &str) -> Result<()> {
+    if !BUILDING.write().await.insert((user, challenge)) {
+        bail!("challenge {challenge} build task for user {user:?} has already started.");
+    }
+
+    let result = async {
+        let entry = get_challenge(db, challenge).await?;
+        let pooled = entry.dynamic && user.is_none();
+
+        let old_artifact = match pooled {
+            true => None,
+            false => get_artifact(db, challenge, user).await.ok(),
+        };
+
+        let target = CONFIG.artifact_root.join(name);
+
+        fs::create_dir_all(&CONFIG.artifact_root).await?;
+        let mut log = File::create(build_log_path(name)).await?;
+
+        let path = CONFIG.challenge_root.join(&entry.path);
+        let source = path.join("source");
+
+        let flag = entry.flag.clone();
+
+        let flag = match entry.dynamic {
+            true => generate_random_flag(&flag)?,
+            false => flag,
+        };
+
+        let username = match user {
+            Some(user) => Some(get_user(db, user).await?.username),
+            None => None,
+        };
+
+        let seed = generate_seed(&entry, user);
+
+        let vars = BuildVars {
+            flag: flag.clone(),
+            user_id: user,
+            username,
+            challenge_id: challenge,
+            seed: seed.clone(),
+        };
+
+        let result = conductor::build(
+            &source,
+            &target,
+            true,
+            &vars,
+            &CONFIG.build.limits,
+            &mut log,
+        )
+        .await?;
+
+        let artifact = ArtifactEntry {
+            id: None,
+            user,
+            challenge,
+            flag,
+            path: name.to_string(),
+            info: result.artifacts.into(),
+            pooled,
+            seed: Some(seed),
+        };
+
+        update_artifact(db, artifact).await?;
+
+        if let Some(artifact) = old_artifact {
+            clear_artifact(&artifact).await;
+            _ = delete_artifact(db, artifact.id.unwrap()).await;
+        }
+
+        Ok(())
+    }
+    .await;
+
+    BUILDING.write().await.remove(&(user, challenge));
+
+    result
+}
+
+async fn run_build(db: &Db, mut build: Build) -> Result<()> {
+    let name = uuid::Uuid::new_v4().hyphenated().to_string();
+
+    build.status = BuildStatus::Running;
+    build.path = Some(name.clone());
+    build.error = None;
+
+    update_build(db, build.clone()).await?;
+
+    let result = execute_build(db, build.user, build.challenge, &name).await;
+
+    match &result {
+        Ok(_) => build.status = BuildStatus::Succeeded,
+        Err(err) => {
+            build.status = BuildStatus::Failed;
+            build.error = Some(format!("{err}"));
+        }
+    }
+
+    let refill = result.is_ok() && build.user.is_none();
+    let challenge = build.challenge;
+
+    update_build(db, build).await?;
+
+    if refill {
+        refill_pool(db, challenge).await?;
+    }
+
+    result
+}
+
+async fn new_build(db: &Db, user: Option<i32>, challenge: i32) -> Result<Build> {
+    let entry = get_challenge(db, challenge).await?;
+
+    if entry.dynamic && user.is_none() {
+        bail!("user id is needed for dynamic challenge.");
+    }
+
+    if !entry.dynamic && user.is_some() {
+        bail!("static challenge should not have a user id assigned.");
+    }
+
+    Ok(Build {
+        id: None,
+        user,
+        challenge,
+        status: BuildStatus::Queued,
+        path: None,
+        error: None,
+        time: primitive_now(),
+    })
+}
+
+pub async fn build_challenge(db: &Db, user: Option<i32>, challenge: i32) -> Result<()> {
+    let mut build = new_build(db, user, challenge).await?;
+    build.status = BuildStatus::Running;
+    build.id = Some(add_build(db, build.clone()).await?);
+
+    run_build(db, build).await
+}
+
+async fn queue_build(db: &Db, mut build: Build) -> Result<Option<Build>> {
+    build.id = add_build_if_idle(db, build.clone()).await?;
+
+    if build.id.is_none() {
+        return Ok(None);
+    }
+
+    BUILD_NOTIFY.notify_one();
+
+    Ok(Some(build))
+}
+
+fn pool_size(challenge: &Challenge) -> usize {
+    match challenge.dynamic {
+        true => challenge
+            .pool
+            .map(|size| size as usize)
+            .unwrap_or(CONFIG.build.pool),
+        false => 0,
+    }
+}
+
+pub async fn refill_pool(db: &Db, challenge: i32) -> Result<()> {
+    let entry = get_challenge(db, challenge).await?;
+
+    let pending = get_latest_build(db, challenge, None)
+        .await
+        .some()?
+        .is_some_and(|build| build.status.is_pending());
+
+    let pooled = count_pooled_artifacts(db, challenge).await? as usize;
+
+    if pending || pooled >= pool_size(&entry) {
+        return Ok(());
+    }
+
+    let source = CONFIG.challenge_root.join(&entry.path).join("source");
+
+    if conductor::uses_user_vars(&source).await? {
+        log::info!(target: "challenge", "challenge {challenge} refers to user variables, pooling is skipped.");
+        return Ok(());
+    }
+
+    // pool builds are queued one at a time, and the next one is queued once it succeeded.
+    let build = Build {
+        id: None,
+        user: None,
+        challenge,
+        status: BuildStatus::Queued,
+        path: None,
+        error: None,
+        time: primitive_now(),
+    };
+
+    queue_build(db, build).await?;
+
+    Ok(())
+}
+
+pub async fn refill_pools(db: &Db) -> Result<()> {
+    let challenges = list_challenges(db).await?;
+
+    for challenge in challenges {
+        refill_pool(db, challenge.id.unwrap()).await?;
+    }
+
+    Ok(())
+}
+
+async fn assign_pooled_artifact(db: &Db, user: i32, mut build: Build) -> Result<Option<Build>> {
+    let challenge = build.challenge;
+
+    let old_artifact = get_artifact(db, challenge, Some(user)).await.some()?;
+
+    let artifact = match claim_pooled_artifact(db, challenge, user).await.some()? {
+        Some(artifact) => artifact,
+        None => return Ok(None),
+    };
+
+    if let Some(artifact) = old_artifact {
+        clear_artifact(&artifact).await;
+        _ = delete_artifact(db, artifact.id.unwrap()).await;
+    }
+
+    build.status = BuildStatus::Succeeded;
+    build.path = Some(artifact.path);
+    build.id = Some(add_build(db, build.clone()).await?);
+
+    if let Err(e) = refill_pool(db, challenge).await {
+        log::error!(target: "challenge", "failed to refill pool of challenge {challenge}: {e:?}")
+    }
+
+    Ok(Some(build))
+}
+
+pub async fn enqueue_build(db: &Db, user: Option<i32>, challenge: i32) -> Result<Build> {
+    let build = new_build(db, user, challenge).await?;
+
+    let pending = get_latest_build(db, challenge, user)
+        .await
+        .some()?
+        .is_some_and(|build| build.status.is_pending());
+
+    if !pending {
+        if let Some(user) = user {
+            if let Some(build) = assign_pooled_artifact(db, user, build.clone()).await? {
+                return Ok(build);
+            }
+        }
+    }
+
+    queue_build(db, build).await?.ok_or_else(|| {
+        anyhow!("challenge {challenge} build task for user {user:?} has already been queued.")
+    })
+}
+
+pub async fn retry_build(db: &Db, id: i32) -> Result<()> {
+    let build = get_build(db, id).await?;
+
+    if build.status != BuildStatus::Failed {
+        bail!("only failed build can be retried.");
+    }
+
+    let entry = get_challenge(db, build.challenge).await?;
+
+    match entry.dynamic && build.user.is_none() {
+        true => refill_pool(db, build.challenge).await?,
+        false => _ = enqueue_build(db, build.user, build.challenge).await?,
+    }
+
+    remove_build(db, id).await?;
+
+    Ok(())
+}
+
+pub async fn remove_build(db: &Db, id: i32) -> Result<()> {
+    let build = get_build(db, id).await?;
+
+    match build.status {
+        BuildStatus::Queued | BuildStatus::Running => bail!("build is still pending."),
+        // log of succeeded build belongs to its artifact.
+        BuildStatus::Failed => {
+            if let Some(path) = &build.path {
+                _ = fs::remove_file(build_log_path(path)).await;
+            }
+        }
+        BuildStatus::Succeeded => {}
+    }
+
+    delete_build(db, id).await?;
+
+    Ok(())
+}
+
+async fn build_worker(pool: DbPool) {
+    loop {
+        let db = match Db::from_pool(&pool).await {
+            Some(db) => db,
+            None => {
+                log::error!(target: "challenge", "build worker failed to get database connection");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        match claim_queued_build(&db).await.some() {
+            Ok(Some(build)) => {
+                let id = build.id.unwrap();
+
+                if let Err(e) = run_build(&db, build).await {
+                    log::warn!(target: "challenge", "build {id} failed: {e:?}")
+                }
+            }
+            Ok(None) => {
+                drop(db);
+                BUILD_NOTIFY.notified().await;
+            }
+            Err(e) => {
+                log::error!(target: "challenge", "failed to claim queued build: {e:?}");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+async fn start_build_workers(pool: DbPool) {
+    let db = Db::from_pool(&pool).await.expect("database connection");
+    requeue_running_builds(&db)
+        .await
+        .expect("failed to requeue interrupted builds.");
+
+    refill_pools(&db).await.expect("failed to refill pools.");
+
+    for _ in 0..CONFIG.build.workers {
+        tokio::spawn(build_worker(pool.clone()));
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildState {
+    pub build: Build,
+    pub position: Option<i64>,
+}
+
+pub async fn get_build_state(
+    db: &Db,
+    user: Option<i32>,
+    challenge: i32,
+) -> Result<Option<BuildState>> {
+    let build = match get_latest_build(db, challenge, user).await.some()? {
+        Some(build) => build,
+        None => return Ok(None),
+    };
+
+    let position = match build.status {
+        BuildStatus::Queued => Some(count_queued_builds_before(db, build.id.unwrap()).await?),
+        _ => None,
+    };
+
+    Ok(Some(BuildState { build, position }))
+}
+
+pub async fn read_build_log(build: &Build) -> Result<String> {
+    let name = build
+        .path
+        .as_ref()
+        .ok_or_else(|| anyhow!("build not started."))?;
+    let content = fs::read(build_log_path(name)).await?;
+
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+pub async fn open_build_log(build: &Build) -> Result<NamedFile<File>> {
+    let name = build
+        .path
+        .as_ref()
+        .ok_or_else(|| anyhow!("build not started."))?;
+
+    Ok(NamedFile::open(build_log_path(name)).await?)
+}
+
+fn proxied_ports(artifact: &Artifact) -> Vec<String> {
+    match artifact {
+        Artifact::Docker(docker) => docker.config.proxied.clone(),
+        Artifact::Compose(compose) => compose
+            .services
+            .iter()
+            .flat_map(|service| {
+                service
+                    .config
+                    .proxied
+                    .iter()
+                    .map(|exposed| format!("{}:{exposed}", service.name))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn artifact_expiry(artifact: &Artifact) -> Option<Duration> {
+    let expiry = match artifact {
+        Artifact::Docker(docker) => docker.config.resources.expiry,
+        Artifact::Compose(compose) => compose
+            .services
+            .iter()
+            .filter_map(|service| service.config.resources.expiry)
+            .max(),
+        _ => None,
+    }
+    .or(CONFIG.docker.expiry)?;
+
+    match CONFIG.docker.max_lifetime {
+        Some(max) => Some(expiry.min(max)),
+        None => Some(expiry),
+    }
+}
+
+fn create_routes(info: &RunDockerResult, proxied: &[String]) -> HashMap<String, Route> {
+    info.upstreams
+        .keys()
+        .filter_map(|exposed| {
+            let kind = match proxied.contains(exposed) {
+                true if CONFIG.proxy.is_some() => RouteKind::Http,
+                _ if CONFIG.gateway.is_some() => RouteKind::Tcp,
+                _ => return None,
+            };
+
+            let route = Route {
+                kind,
+                token: uuid::Uuid::new_v4().as_simple().to_string(),
+            };
+
+            Some((exposed.clone(), route))
+        })
+        .collect()
+}
+
+async fn add_routes(info: &RunDockerResult, routes: &HashMap<String, Route>) -> Result<()> {
+    for (exposed, route) in routes {
+        let target = match info.upstreams.get(exposed) {
+            Some(upstream) => *upstream,
+            None => {
+                let port = info
+                    .ports
+                    .get(exposed)
+                    .ok_or_else(|| anyhow!("routed port '{exposed}' is not exposed."))?;
+
+                conductor::local_addr(&CONFIG.docker.options, *port)?
+            }
+        };
+
+        proxy::add_route(route, target).await;
+    }
+
+    Ok(())
+}
+
+fn proxy_url(token: &str) -> Option<String> {
+    let config = CONFIG.proxy.as_ref()?;
+
+    let port = config
+        .port
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+
+    Some(format!(
+        "{}://{token}.{}{port}",
+        config.scheme, config.domain
+    ))
+}
+
+fn gateway_connection(token: &str) -> Option<String> {
+    let config = CONFIG.gateway.as_ref()?;
+
+    let port = config.port.unwrap_or(config.listen.port());
+
+    Some(format!("{token}@{}:{port}", config.host))
+}
+
+fn to_primitive(instant: Instant) -> PrimitiveDateTime {
+    let now = Instant::now();
+
+    match instant.checked_duration_since(now) {
+        Some(duration) => primitive_now() + duration,
+        None => primitive_now() - now.duration_since(instant),
+    }
+}
+
+fn to_instant(datetime: PrimitiveDateTime) -> Instant {
+    let now = Instant::now();
+    let duration = datetime - primitive_now();
+
+    match duration.is_negative() {
+        true => now.checked_sub(duration.unsigned_abs()),
+        false => now.checked_add(duration.unsigned_abs()),
+    }
+    .unwrap_or(now)
+}
+
+async fn reconcile_instances(db: &Db) -> Result<()> {
+    let now = Instant::now();
+    let mut adopted = Vec::new();
+
+    for entry in list_instances(db).await? {
+        let id = entry.id.expect("id should be present.");
+        let info = entry.info.0;
+
+        let stop_at = entry.stop_at.map(to_instant);
+        let expired = stop_at.is_some_and(|stop_at| stop_at <= now);
+
+        if !expired && conductor::is_docker_alive(&info).await? {
+            let routes = entry.routes.0;
+
+            conductor::reserve_ports(&info);
+
+            if let Err(e) = add_routes(&info, &routes).await {
+                log::error!(target: "challenge", "failed to restore routes of instance {id}: {e:?}")
+            }
+
+            let instance = DockerInstance {
+                id,
+                info: info.clone(),
+                routes,
+                start_at: to_instant(entry.start_at),
+                stop_at,
+                closed: Default::default(),
+            };
+
+            DOCKER_INSTANCES
+                .insert(
+                    (entry.user, entry.challenge, entry.artifact as usize),
+                    instance,
+                )
+                .await;
+
+            adopted.push(info);
+            continue;
+        }
+
+        if let Err(e) = conductor::stop_docker(&info).await {
+            log::error!(target: "challenge", "failed to stop stale docker instance {id}: {e:?}")
+        }
+
+        delete_instance(db, id).await?;
+    }
+
+    log::info!(target: "challenge", "{} docker instances re-adopted.", adopted.len());
+
+    let artifacts: Vec<_> = list_artifacts(db)
+        .await?
+        .into_iter()
+        .flat_map(|artifact| artifact.info.0)
+        .collect();
+
+    conductor::retain_shared_images(&artifacts);
+
+    let images: Vec<_> = artifacts
+        .into_iter()
+        .flat_map(|info| match info {
+            Artifact::Docker(docker) => vec![docker.id],
+            Artifact::Compose(compose) => compose
+                .services
+                .into_iter()
+                .filter(|service| service.built)
+                .map(|service| service.id)
+                .collect(),
+            _ => vec![],
+        })
+        .collect();
+
+    conductor::remove_orphans(&adopted, &images).await
+}
+
+async fn restore_instances(pool: &DbPool) {
+    _ = INSTANCE_DB_POOL.set(pool.clone());
+
+    let db = Db::from_pool(pool).await.expect("database connection");
+
+    if let Err(e) = reconcile_instances(&db).await {
+        log::error!(target: "challenge", "failed to reconcile docker instances: {e:?}")
+    }
+}
+
+pub async fn is_docker_running(user: Option<i32>, challenge: i32, artifact: usize) -> bool {
+    DOCKER_INSTANCES.contains_key(&(user, challenge, artifact))
+}
+
+fn count_docker_instances(preparing: &HashSet<ArtifactIndex>, user: i32) -> (usize, usize) {
+    let mut user_count = 0;
+    let mut total = 0;
+
+    let running = DOCKER_INSTANCES.iter().map(|(idx, _)| *idx);
+    let preparing = preparing
+        .iter()
+        .copied()
+        .filter(|idx| !DOCKER_INSTANCES.contains_key(idx));
+
+    for idx in running.chain(preparing) {
+        total += 1;
+
+        if idx.0 == Some(user) {
+            user_count += 1;
+        }
+    }
+
+    (user_count, total)
+}
+
+pub async fn get_docker_instance_usage(user: i32) -> DockerInstanceUsage {
+    let (user_count, total) = count_docker_instances(&*DOCKER_PREPARING.read().await, user);
+
+    DockerInstanceUsage {
+        user: user_count,
+        user_limit: CONFIG.docker.max_per_user,
+        total,
+        total_limit: CONFIG.docker.max_total,
+    }
+}
+
+pub async fn run_docker(db: &Db, user: Option<i32>, challenge: i32, artifact: usize) -> Result<()> {
+    {
+        let mut preparing = DOCKER_PREPARING.write().await;
+
+        if preparing.contains(&(user, challenge, artifact)) {
+            bail!("docker {artifact} of challenge {challenge} is preparing.");
+        }
+
+        if let Some(user) =
+            user.filter(|_| !DOCKER_INSTANCES.contains_key(&(user, challenge, artifact)))
+        {
+            let (user_count, total) = count_docker_instances(&preparing, user);
+
+            if CONFIG
+                .docker
+                .max_per_user
+                .is_some_and(|max| user_count >= max)
+            {
+                bail!("too many docker instances running for the user, stop one and try again.");
+            }
+
+            if CONFIG.docker.max_total.is_some_and(|max| total >= max) {
+                bail!("server is full, please try again later.");
+            }
+        }
+
+        preparing.insert((user, challenge, artifact));
+    }
+
+    let result = async {
+        if is_docker_running(user, challenge, artifact).await {
+            bail!("a docker instance is already running.");
+        }
+
+        let entry = get_challenge(db, challenge).await?;
+
+        match user {
+            Some(_) if entry.shared => {
+                bail!("instance of a shared challenge is managed by admins.")
+            }
+            None if !entry.shared => bail!("challenge is not shared."),
+            _ => {}
+        }
+
+        let security = entry.hardened.then_some(&CONFIG.docker.security);
+        let entry = get_artifact(db, challenge, entry.dynamic.then_some(user).flatten()).await?;
+
+        let info = entry
+            .info
+            .0
+            .into_iter()
+            .nth(artifact)
+            .ok_or_else(|| anyhow!("artifact not found."))?;
+
+        let proxied = proxied_ports(&info);
+        // shared instances are long-lived and only stopped by admins.
+        let expiry = user.and_then(|_| artifact_expiry(&info));
+
+        let info = match &info {
+            Artifact::Docker(docker) => {
+                conductor::run_docker(docker, &CONFIG.docker.options, security, &entry.flag).await?
+            }
+            Artifact::Compose(compose) => {
+                conductor::run_compose(compose, &CONFIG.docker.options, security, &entry.flag)
+                    .await?
+            }
+            _ => bail!("unexpected artifact type got."),
+        };
+
+        let start_at = Instant::now();
+
+        let stop_at = expiry.and_then(|expiry| start_at.checked_add(expiry));
+
+        let routes = create_routes(&info, &proxied);
+
+        let entry = InstanceEntry {
+            id: None,
+            user,
+            challenge,
+            artifact: artifact as i32,
+            info: info.clone().into(),
+            start_at: to_primitive(start_at),
+            stop_at: stop_at.map(to_primitive),
+            routes: routes.clone().into(),
+        };
+
+        let result = async {
+            add_routes(&info, &routes).await?;
+            add_instance(db, entry).await
+        }
+        .await;
+
+        let id = match result {
+            Ok(id) => id,
+            Err(err) => {
+                for route in routes.values() {
+                    proxy::remove_route(route).await;
+                }
+
+                _ = conductor::stop_docker(&info).await;
+                return Err(err);
+            }
+        };
+
+        let instance = DockerInstance {
+            id,
+            info,
+            routes,
+            start_at,
+            stop_at,
+            closed: Default::default(),
+        };
+
+        DOCKER_INSTANCES
+            .insert((user, challenge, artifact), instance)
+            .await;
+
+        let idx = (user, challenge, artifact);
+
+        DOCKER_ACTIVITIES.lock().await.remove(&idx);
+        DOCKER_REAPED.write().await.remove(&idx);
+
+        Ok(())
+    }
+    .await;
+
+    DOCKER_PREPARING
+        .write()
+        .await
+        .remove(&(user, challenge, artifact));
+
+    result
+}
+
+pub async fn extend_docker(
+    db: &Db,
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+    privileged: bool,
+) -> Result<()> {
+    let result = DOCKER_INSTANCES
+        .entry((user, challenge, artifact))
+        .and_compute_with(|entry| async move {
+            let Some(entry) = entry else {
+                return Op::Nop;
+            };
+
+            let mut instance = entry.into_value();
+
+            match instance.extended_stop_at(privileged) {
+                Some(stop_at) => {
+                    instance.stop_at = Some(stop_at);
+                    Op::Put(instance)
+                }
+                None => Op::Nop,
+            }
+        })
+        .await;
+
+    match result {
+        CompResult::ReplacedWith(entry) => {
+            let instance = entry.into_value();
+
+            touch_docker((user, challenge, artifact)).await;
+            update_instance_stop_at(db, instance.id, instance.stop_at.map(to_primitive)).await?;
+
+            Ok(())
+        }
+        CompResult::StillNone(_) => bail!("docker instance not found."),
+        _ => bail!("docker instance cannot be extended anymore."),
+    }
+}
+
+pub async fn stop_docker(user: Option<i32>, challenge: i32, artifact: usize) {
+    DOCKER_INSTANCES
+        .invalidate(&(user, challenge, artifact))
+        .await;
+}
+
+async fn check_cooldown(idx: ArtifactIndex, action: DockerAction) -> Result<()> {
+    let Some(cooldown) = CONFIG.docker.cooldown else {
+        return Ok(());
+    };
+
+    let mut cooldowns = DOCKER_COOLDOWNS.lock().await;
+    let now = Instant::now();
+
+    if let Some(remaining) = cooldowns
+        .get(&(idx, action))
+        .and_then(|last| last.checked_add(cooldown))
+        .and_then(|until| until.checked_duration_since(now))
+    {
+        bail!(
+            "operating too frequently, try again in {} secs.",
+            remaining.as_secs() + 1
+        );
+    }
+
+    cooldowns.insert((idx, action), now);
+
+    Ok(())
+}
+
+pub async fn restart_docker(
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+    privileged: bool,
+) -> Result<()> {
+    let instance = DOCKER_INSTANCES
+        .get(&(user, challenge, artifact))
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    if user.is_some() && !privileged {
+        check_cooldown((user, challenge, artifact), DockerAction::Restart).await?;
+    }
+
+    touch_docker((user, challenge, artifact)).await;
+    conductor::restart_docker(&instance.info).await
+}
+
+pub async fn reset_docker(
+    db: &Db,
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+    privileged: bool,
+) -> Result<()> {
+    {
+        let mut preparing = DOCKER_PREPARING.write().await;
+
+        if preparing.contains(&(user, challenge, artifact)) {
+            bail!("docker {artifact} of challenge {challenge} is preparing.");
+        }
+
+        preparing.insert((user, challenge, artifact));
+    }
+
+    let result = async {
+        let instance = DOCKER_INSTANCES
+            .get(&(user, challenge, artifact))
+            .await
+            .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+        if user.is_some() && !privileged {
+            check_cooldown((user, challenge, artifact), DockerAction::Reset).await?;
+        }
+
+        let entry = get_challenge(db, challenge).await?;
+        let security = entry.hardened.then_some(&CONFIG.docker.security);
+        let entry = get_artifact(db, challenge, entry.dynamic.then_some(user).flatten()).await?;
+
+        let info = entry
+            .info
+            .0
+            .into_iter()
+            .nth(artifact)
+            .ok_or_else(|| anyhow!("artifact not found."))?;
+
+        for route in instance.routes.values() {
+            proxy::remove_route(route).await;
+        }
+
+        let options = &CONFIG.docker.options;
+
+        let info = match &info {
+            Artifact::Docker(docker) => {
+                conductor::reset_docker(docker, &instance.info, options, security, &entry.flag)
+                    .await
+            }
+            Artifact::Compose(compose) => {
+                conductor::reset_compose(compose, &instance.info, options, security, &entry.flag)
+                    .await
+            }
+            _ => Err(anyhow!("unexpected artifact type got.")),
+        };
+
+        let info = match info {
+            Ok(info) => info,
+            Err(err) => {
+                // the previous containers are gone already, drop the instance as well.
+                DOCKER_INSTANCES
+                    .invalidate(&(user, challenge, artifact))
+                    .await;
+                return Err(err);
+            }
+        };
+
+        let id = instance.id;
+        let routes = instance.routes.clone();
+
+        // sessions attached to the previous containers are gone with them.
+        instance.closed.notify_waiters();
+
+        let instance = DockerInstance {
+            info: info.clone(),
+            closed: Default::default(),
+            ..instance
+        };
+
+        DOCKER_INSTANCES
+            .insert((user, challenge, artifact), instance)
+            .await;
+
+        touch_docker((user, challenge, artifact)).await;
+
+        let result = async {
+            add_routes(&info, &routes).await?;
+            update_instance_info(db, id, info).await?;
+            Ok(())
+        }
+        .await;
+
+        if result.is_err() {
+            DOCKER_INSTANCES
+                .invalidate(&(user, challenge, artifact))
+                .await;
+        }
+
+        result
+    }
+    .await;
+
+    DOCKER_PREPARING
+        .write()
+        .await
+        .remove(&(user, challenge, artifact));
+
+    result
+}
+
+async fn touch_docker(idx: ArtifactIndex) {
+    let mut activities = DOCKER_ACTIVITIES.lock().await;
+
+    if let Some(activity) = activities.get_mut(&idx) {
+        activity.last = Instant::now();
+    }
+}
+
+async fn docker_last_activity(idx: ArtifactIndex, instance: &DockerInstance) -> Instant {
+    // ports published directly are not seen by the proxy or gateway,
+    // so fall back to the network counters of the containers.
+    let network = match instance
+        .info
+        .ports
+        .keys()
+        .all(|exposed| instance.routes.contains_key(exposed))
+    {
+        true => None,
+        false => conductor::get_docker_stats(&instance.info)
+            .await
+            .ok()
+            .map(|stats| stats.network),
+    };
+
+    let mut activities = DOCKER_ACTIVITIES.lock().await;
+    let now = Instant::now();
+
+    let activity = activities
+        .entry(idx)
+        .or_insert(DockerActivity { network, last: now });
+
+    if network.is_some() && activity.network != network {
+        activity.network = network;
+        activity.last = now;
+    }
+
+    instance
+        .routes
+        .values()
+        .filter_map(proxy::last_activity)
+        .fold(activity.last, Instant::max)
+}
+
+async fn reap_idle_dockers(timeout: Duration) {
+    DOCKER_ACTIVITIES
+        .lock()
+        .await
+        .retain(|idx, _| DOCKER_INSTANCES.contains_key(idx));
+
+    for (idx, instance) in DOCKER_INSTANCES.iter() {
+        // shared instances are managed by admins and never reaped.
+        if idx.0.is_none() || DOCKER_PREPARING.read().await.contains(&idx) {
+            continue;
+        }
+
+        let last = docker_last_activity(*idx, &instance).await;
+
+        if last.elapsed() < timeout {
+            continue;
+        }
+
+        log::info!(
+            target: "challenge",
+            "reaping idle docker {} of challenge {} for user {:?}",
+            idx.2,
+            idx.1,
+            idx.0
+        );
+
+        DOCKER_REAPED.write().await.insert(*idx);
+        DOCKER_INSTANCES.invalidate(&idx).await;
+    }
+}
+
+async fn idle_reaper(timeout: Duration) {
+    let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL.min(timeout));
+
+    loop {
+        interval.tick().await;
+        reap_idle_dockers(timeout).await;
+    }
+}
+
+pub async fn is_docker_reaped(user: i32, challenge: i32, artifact: usize) -> bool {
+    DOCKER_REAPED
+        .read()
+        .await
+        .contains(&(Some(user), challenge, artifact))
+}
+
+pub async fn stop_dockers(user: Option<i32>, challenge: i32) {
+    for (idx, _) in DOCKER_INSTANCES.iter() {
+        if user.map(|x| idx.0 == Some(x)).unwrap_or(true) && idx.1 == challenge {
+            DOCKER_INSTANCES.invalidate(&idx).await;
+        }
+    }
+}
+
+pub async fn stop_active_sessions(user: Option<i32>, challenge: i32) {
+    stop_dockers(user, challenge).await;
+}
+
+fn mapped_addr(addr: &MappedAddr, port: u16) -> SocketAddr {
+    let port = addr
+        .ports
+        .as_ref()
+        .map(|mapped| {
+            let ports = CONFIG
+                .docker
+                .options
+                .ports
+                .as_ref()
+                .expect("'ports' should be set here.");
+
+            port - ports.start() + mapped.start()
+        })
+        .unwrap_or(port);
+
+    SocketAddr::new(addr.addr, port)
+}
+
+fn remaining(stop_at: Instant) -> Duration {
+    stop_at.saturating_duration_since(Instant::now())
+}
+
+pub async fn list_docker_instances() -> Vec<DockerInstanceEntry> {
+    let mut instances: Vec<_> = DOCKER_INSTANCES
+        .iter()
+        .map(|(idx, instance)| DockerInstanceEntry {
+            user: idx.0,
+            challenge: idx.1,
+            artifact: idx.2,
+            info: instance.info.clone(),
+            age: instance.start_at.elapsed(),
+            expiry: instance.stop_at.map(remaining),
+        })
+        .collect();
+
+    instances.sort_by_key(|instance| instance.age);
+
+    instances
+}
+
+pub async fn get_docker_instance_stats(
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+) -> Result<DockerStats> {
+    let instance = DOCKER_INSTANCES
+        .get(&(user, challenge, artifact))
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    conductor::get_docker_stats(&instance.info).await
+}
+
+pub async fn read_docker_instance_logs(
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+    tail: usize,
+) -> Result<String> {
+    let instance = DOCKER_INSTANCES
+        .get(&(user, challenge, artifact))
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    conductor::read_docker_logs(&instance.info, tail).await
+}
+
+pub async fn get_docker_instance_info(
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+) -> Result<DockerInstanceInfo> {
+    let instance = DOCKER_INSTANCES
+        .get(&(user, challenge, artifact))
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    let expiry = instance.stop_at.map(remaining);
+
+    let ports: Vec<_> = instance
+        .info
+        .ports
+        .clone()
+        .into_iter()
+        .filter(|(exposed, _)| {
+            !instance
+                .routes
+                .get(exposed)
+                .is_some_and(|route| route.kind == RouteKind::Tcp)
+        })
+        .map(|(exposed, port)| {
+            (
+                exposed,
+                CONFIG
                     .docker
                     .mapped_addrs
                     .iter()
@@ -555,7 +2343,29 @@ pub async fn get_docker_instance_info(
         })
         .collect();
 
-    Ok(DockerInstanceInfo { expiry, ports })
+    let extendable = instance.extended_stop_at(false).is_some();
+
+    let urls = instance
+        .routes
+        .iter()
+        .filter(|(_, route)| route.kind == RouteKind::Http)
+        .filter_map(|(exposed, route)| Some((exposed.clone(), proxy_url(&route.token)?)))
+        .collect();
+
+    let connections = instance
+        .routes
+        .iter()
+        .filter(|(_, route)| route.kind == RouteKind::Tcp)
+        .filter_map(|(exposed, route)| Some((exposed.clone(), gateway_connection(&route.token)?)))
+        .collect();
+
+    Ok(DockerInstanceInfo {
+        expiry,
+        extendable,
+        ports,
+        urls,
+        connections,
+    })
 }
 
 pub async fn open_binary(
@@ -600,8 +2410,35 @@ pub async fn open_attachment(
     Ok(NamedFile::open(path).await?)
 }
 
+fn append_states<W: Write>(
+    tar: &mut tar::Builder<W>,
+    root: &Path,
+    relative: &Path,
+    secrets: &[String],
+) -> Result<()> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let relative = relative.join(entry?.file_name());
+
+        // injected flags are kept out of the states handed to players.
+        if secrets.iter().any(|secret| Path::new(secret) == relative) {
+            continue;
+        }
+
+        let path = root.join(&relative);
+
+        if path.is_dir() {
+            tar.append_dir(&relative, &path)?;
+            append_states(tar, root, &relative, secrets)?;
+        } else {
+            tar.append_path_with_name(&path, &relative)?;
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn open_docker_states(
-    user: i32,
+    user: Option<i32>,
     challenge: i32,
     artifact: usize,
 ) -> Result<NamedFile<Cursor<Vec<u8>>>> {
@@ -615,7 +2452,12 @@ pub async fn open_docker_states(
     {
         let enc = GzEncoder::new(&mut tarfile, Compression::default());
         let mut tar = tar::Builder::new(enc);
-        tar.append_dir_all("", &instance.info.states)?;
+        append_states(
+            &mut tar,
+            &instance.info.states,
+            Path::new(""),
+            &instance.info.secrets,
+        )?;
         tar.finish()?;
     }
 
@@ -623,6 +2465,287 @@ pub async fn open_docker_states(
     Ok(NamedFile::with_name(&filename, Cursor::new(tarfile)))
 }
 
+fn is_secret(relative: &Path, secrets: &[String]) -> bool {
+    secrets
+        .iter()
+        .any(|secret| relative.starts_with(Path::new(secret)))
+}
+
+fn unpack_states<R: Read>(archive: R, root: &Path, secrets: &[String], limit: u64) -> Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(archive));
+    let mut total = 0;
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("unsafe path '{}' detected.", path.display());
+        }
+
+        let relative: PathBuf = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+
+        // injected flags are never taken from players.
+        if relative.as_os_str().is_empty() || is_secret(&relative, secrets) {
+            continue;
+        }
+
+        let path = root.join(&relative);
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => std::fs::create_dir_all(&path)?,
+            tar::EntryType::Regular => {
+                total += entry.header().size()?;
+
+                if total > limit {
+                    bail!("states exceed the size limit.");
+                }
+
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                entry.unpack(&path)?;
+            }
+            kind => bail!(
+                "unsupported entry type {kind:?} of '{}', only regular files and directories are allowed.",
+                relative.display()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn clear_states(root: &Path, relative: &Path, secrets: &[String]) -> Result<()> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        let path = root.join(&relative);
+
+        // symlinks are removed rather than followed.
+        let is_dir = entry.file_type()?.is_dir();
+
+        if secrets.iter().any(|secret| Path::new(secret) == relative) {
+            continue;
+        }
+
+        if is_dir
+            && secrets
+                .iter()
+                .any(|secret| Path::new(secret).starts_with(&relative))
+        {
+            clear_states(root, &relative, secrets)?;
+            continue;
+        }
+
+        match is_dir {
+            true => std::fs::remove_dir_all(&path)?,
+            false => std::fs::remove_file(&path)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn merge_states(source: &Path, target: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let path = target.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() && path.is_dir() && !path.is_symlink() {
+            merge_states(&entry.path(), &path)?;
+        } else {
+            std::fs::rename(entry.path(), &path)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn restore_docker_states(
+    db: &Db,
+    user: Option<i32>,
+    challenge: i32,
+    artifact: usize,
+    archive: RocketTempFile<'_>,
+) -> Result<()> {
+    let limit = CONFIG.docker.max_states_size;
+
+    if archive.len() > limit {
+        bail!("states archive exceeds the size limit.");
+    }
+
+    let idx = (user, challenge, artifact);
+
+    // a stopped instance is started first, as its states directory comes with it.
+    let started = match DOCKER_INSTANCES.contains_key(&idx) {
+        true => false,
+        false => {
+            run_docker(db, user, challenge, artifact).await?;
+            true
+        }
+    };
+
+    let instance = DOCKER_INSTANCES
+        .get(&idx)
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    let result = async {
+        let archive = match archive {
+            RocketTempFile::File { path, .. } => Either::Left(StdFile::open(path)?),
+            RocketTempFile::Buffered { content } => Either::Right(content),
+        };
+
+        let staging = CONFIG
+            .docker
+            .options
+            .states_root
+            .join(format!("upload-{}", uuid::Uuid::new_v4().as_simple()));
+
+        let root = &instance.info.states;
+        let secrets = &instance.info.secrets;
+
+        let result = (|| {
+            std::fs::create_dir_all(&staging)?;
+            unpack_states(archive, &staging, secrets, limit)?;
+
+            // the directory is bind mounted, so its content is replaced in place.
+            clear_states(root, Path::new(""), secrets)?;
+            merge_states(&staging, root)
+        })();
+
+        if staging.exists() {
+            fs::remove_dir_all(&staging).await?;
+        }
+
+        result?;
+
+        // the freshly started containers are restarted to pick up the restored states.
+        if started {
+            conductor::restart_docker(&instance.info).await?;
+        }
+
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() && started {
+        stop_docker(user, challenge, artifact).await;
+    }
+
+    result
+}
+
+pub struct DockerTerminal {
+    idx: ArtifactIndex,
+    terminal: Terminal,
+    closed: Arc<Notify>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TerminalControl {
+    Resize { rows: u16, cols: u16 },
+}
+
+pub async fn open_docker_terminal(
+    db: &Db,
+    user: i32,
+    challenge: i32,
+    artifact: usize,
+) -> Result<DockerTerminal> {
+    let idx = (Some(user), challenge, artifact);
+
+    let instance = DOCKER_INSTANCES
+        .get(&idx)
+        .await
+        .ok_or_else(|| anyhow!("docker instance not found."))?;
+
+    let entry = get_challenge(db, challenge).await?;
+    let entry = get_artifact(db, challenge, entry.dynamic.then_some(user)).await?;
+
+    let info = entry
+        .info
+        .0
+        .get(artifact)
+        .ok_or_else(|| anyhow!("artifact not found."))?;
+
+    let terminal = conductor::open_terminal(info, &instance.info).await?;
+
+    Ok(DockerTerminal {
+        idx,
+        terminal,
+        closed: instance.closed,
+    })
+}
+
+// binary frames carry the terminal data, text frames carry control messages.
+pub async fn bridge_docker_terminal(socket: WebSocketConnection, terminal: DockerTerminal) {
+    let DockerTerminal {
+        idx,
+        terminal:
+            Terminal {
+                exec,
+                mut output,
+                mut input,
+            },
+        closed,
+    } = terminal;
+
+    let (mut sink, mut stream) = socket.split();
+
+    let mut closed = pin!(closed.notified());
+    closed.as_mut().enable();
+
+    let outbound = async {
+        while let Some(Ok(data)) = output.next().await {
+            if sink.send(Message::Binary(data)).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let inbound = async {
+        while let Some(Ok(message)) = stream.next().await {
+            // typing in the terminal keeps the instance from being reaped as idle.
+            touch_docker(idx).await;
+
+            let result = match message {
+                Message::Binary(data) => input.write_all(&data).await,
+                Message::Text(text) => {
+                    let control = serde_json::from_str(&text);
+
+                    if let (Some(exec), Ok(TerminalControl::Resize { rows, cols })) =
+                        (&exec, control)
+                    {
+                        _ = conductor::resize_terminal(exec, rows, cols).await;
+                    }
+
+                    Ok(())
+                }
+                Message::Close(_) => break,
+                _ => Ok(()),
+            };
+
+            if result.is_err() {
+                break;
+            }
+        }
+    };
+
+    future::select(future::select(pin!(outbound), pin!(inbound)), closed).await;
+
+    _ = sink.close().await;
+}
+
 pub async fn solve_challenge(db: &Db, user: i32, challenge: i32, flag: &str) -> Result<bool> {
     let entry = get_challenge(db, challenge).await?;
 
@@ -687,3 +2810,6 @@ pub fn is_publicly_available(challenge: &Challenge) -> bool {
 
     categorized && challenge.public
 }
+
+#[cfg(test)]
+mod tests;
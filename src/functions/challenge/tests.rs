@@ -0,0 +1,336 @@
+use tokio::sync::OnceCell;
+
+use crate::{
+    core::runtime::{self, MockRuntime},
+    db::{
+        self,
+        models::{User, UserRole},
+        query::{instance::list_instances, solved::get_solved, user::add_user},
+    },
+};
+
+use super::*;
+
+const CHALLENGE_CONFIG: &str = r#"
+docker:
+  options:
+    addrs: [127.0.0.1]
+
+proxy:
+  listen: 127.0.0.1:0
+  domain: attackr.test
+"#;
+
+const EVENT_CONFIG: &str = r#"
+start_at: 2000-01-01 00:00:00.0
+end_at: 2999-01-01 00:00:00.0
+"#;
+
+const DOCKER_BUILD: &str = r#"
+steps:
+  - type: Docker
+    path: web
+    config:
+      exposed: ["80/tcp"]
+      proxied: ["80/tcp"]
+"#;
+
+const COMPOSE_BUILD: &str = r#"
+steps:
+  - type: Compose
+    services:
+      - name: web
+        path: web
+        config:
+          exposed: ["80/tcp"]
+          proxied: ["80/tcp"]
+      - name: cache
+        image: redis:alpine
+"#;
+
+// tests share the instance cache, build workers and working directory, so they run one at a time.
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+static POOL: OnceCell<DbPool> = OnceCell::const_new();
+
+async fn setup() -> DbPool {
+    POOL.get_or_init(|| async {
+        let root = std::env::temp_dir().join(format!("attackr-test-{}", std::process::id()));
+
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("configs")).expect("create dir");
+        std::fs::write(root.join("configs/challenge.yml"), CHALLENGE_CONFIG).expect("write file");
+        std::fs::write(root.join("configs/event.yml"), EVENT_CONFIG).expect("write file");
+
+        // configs and storage roots are resolved against the working directory.
+        std::env::set_current_dir(&root).expect("change dir");
+
+        runtime::init(Box::new(MockRuntime::default()));
+
+        let figment = rocket::Config::figment()
+            .merge(("log_level", "off"))
+            .merge(("databases.database.url", "db.sqlite"));
+
+        let rocket = rocket::custom(figment)
+            .attach(db::stage())
+            .ignite()
+            .await
+            .expect("ignite");
+
+        let pool = Db::pool(&rocket).expect("database pool").clone();
+        _ = INSTANCE_DB_POOL.set(pool.clone());
+
+        pool
+    })
+    .await
+    .clone()
+}
+
+async fn add_test_user(db: &Db) -> i32 {
+    let username = format!("u{}", &uuid::Uuid::new_v4().as_simple().to_string()[..16]);
+
+    let user = User {
+        id: None,
+        email: format!("{username}@attackr.test"),
+        username,
+        password: String::new(),
+        contact: "test".to_string(),
+        enabled: true,
+        role: UserRole::Challenger,
+        nickname: None,
+    };
+
+    add_user(db, user).await.expect("add user")
+}
+
+async fn add_test_challenge(db: &Db, dynamic: bool, build: &str) -> i32 {
+    let path = uuid::Uuid::new_v4().hyphenated().to_string();
+    let source = CONFIG.challenge_root.join(&path).join("source");
+
+    fs::create_dir_all(source.join("web"))
+        .await
+        .expect("create dir");
+    fs::write(source.join("build.yml"), build)
+        .await
+        .expect("write file");
+    fs::write(source.join("web/Dockerfile"), "FROM scratch\n")
+        .await
+        .expect("write file");
+
+    let challenge = Challenge {
+        id: None,
+        name: path.clone(),
+        description: String::new(),
+        path,
+        initial: 100.0,
+        points: 100.0,
+        problemset: None,
+        attachments: Vec::new().into(),
+        flag: match dynamic {
+            true => "flag{{{}}}".to_string(),
+            false => "flag{static}".to_string(),
+        },
+        dynamic,
+        public: true,
+        difficulty: None,
+        pool: None,
+        hardened: true,
+        shared: false,
+        slug: None,
+    };
+
+    add_challenge(db, challenge).await.expect("add challenge")
+}
+
+async fn wait_build(db: &Db, user: Option<i32>, challenge: i32) -> Build {
+    for _ in 0..100 {
+        let build = get_latest_build(db, challenge, user)
+            .await
+            .expect("get build");
+
+        if !build.status.is_pending() {
+            return build;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("build of challenge {challenge} did not finish in time.");
+}
+
+async fn start_instance(db: &Db) -> (ArtifactIndex, DockerInstance) {
+    let user = add_test_user(db).await;
+    let challenge = add_test_challenge(db, true, COMPOSE_BUILD).await;
+
+    build_challenge(db, Some(user), challenge)
+        .await
+        .expect("build challenge");
+
+    run_docker(db, Some(user), challenge, 0)
+        .await
+        .expect("run docker");
+
+    let idx = (Some(user), challenge, 0);
+    let instance = DOCKER_INSTANCES.get(&idx).await.expect("running instance");
+
+    (idx, instance)
+}
+
+async fn assert_cleaned(db: &Db, instance: &DockerInstance) {
+    let runtime = runtime::runtime();
+
+    for id in &instance.info.containers {
+        let inspect = runtime.inspect_container(id).await.expect("inspect");
+        assert!(inspect.is_none(), "container {id} is left behind.");
+    }
+
+    let networks = runtime
+        .list_networks(Default::default())
+        .await
+        .expect("list networks");
+
+    assert!(!networks
+        .iter()
+        .any(|network| Some(network) == instance.info.network.as_ref()));
+
+    for route in instance.routes.values() {
+        assert!(proxy::last_activity(route).is_none());
+    }
+
+    assert!(!instance.info.states.exists());
+
+    let instances = list_instances(db).await.expect("list instances");
+    assert!(!instances.iter().any(|entry| entry.id == Some(instance.id)));
+}
+
+#[rocket::async_test]
+async fn queued_build_is_claimed_and_saved() {
+    let _lock = LOCK.lock().await;
+    let pool = setup().await;
+    let db = Db::from_pool(&pool).await.expect("database connection");
+
+    start_build_workers(pool.clone()).await;
+
+    let challenge = add_test_challenge(&db, false, DOCKER_BUILD).await;
+
+    enqueue_build(&db, None, challenge)
+        .await
+        .expect("enqueue build");
+
+    let build = wait_build(&db, None, challenge).await;
+    assert_eq!(build.status, BuildStatus::Succeeded, "{:?}", build.error);
+
+    let artifact = get_artifact(&db, challenge, None)
+        .await
+        .expect("get artifact");
+
+    assert!(!artifact.pooled);
+    assert!(build_log_path(&artifact.path).exists());
+
+    let Some(Artifact::Docker(docker)) = artifact.info.first() else {
+        panic!("unexpected artifacts {:?}.", artifact.info);
+    };
+
+    let image = runtime::runtime()
+        .inspect_image(&docker.id)
+        .await
+        .expect("inspect image");
+
+    assert!(image.is_some());
+}
+
+#[rocket::async_test]
+async fn running_instance_is_registered() {
+    let _lock = LOCK.lock().await;
+    let pool = setup().await;
+    let db = Db::from_pool(&pool).await.expect("database connection");
+
+    let (idx, instance) = start_instance(&db).await;
+
+    assert!(is_docker_running(idx.0, idx.1, idx.2).await);
+    assert_eq!(instance.info.containers.len(), 2);
+    assert!(instance.info.network.is_some());
+    assert!(instance.info.states.is_dir());
+
+    for id in &instance.info.containers {
+        let running = runtime::runtime()
+            .inspect_container(id)
+            .await
+            .expect("inspect")
+            .is_some_and(|info| info.running);
+
+        assert!(running);
+    }
+
+    let route = instance.routes.get("web:80/tcp").expect("web route");
+    assert_eq!(route.kind, RouteKind::Http);
+    assert!(proxy::last_activity(route).is_some());
+
+    let info = get_docker_instance_info(idx.0, idx.1, idx.2)
+        .await
+        .expect("instance info");
+
+    assert_eq!(info.urls.len(), 1);
+
+    let instances = list_instances(&db).await.expect("list instances");
+    assert!(instances.iter().any(|entry| entry.id == Some(instance.id)));
+
+    stop_docker(idx.0, idx.1, idx.2).await;
+}
+
+#[rocket::async_test]
+async fn submitted_flag_solves_challenge() {
+    let _lock = LOCK.lock().await;
+    let pool = setup().await;
+    let db = Db::from_pool(&pool).await.expect("database connection");
+
+    let user = add_test_user(&db).await;
+    let challenge = add_test_challenge(&db, true, DOCKER_BUILD).await;
+
+    build_challenge(&db, Some(user), challenge)
+        .await
+        .expect("build challenge");
+
+    let artifact = get_artifact(&db, challenge, Some(user))
+        .await
+        .expect("get artifact");
+
+    assert!(!solve_challenge(&db, user, challenge, "flag{wrong}")
+        .await
+        .expect("solve challenge"));
+    assert!(get_solved(&db, user, challenge).await.is_err());
+
+    assert!(solve_challenge(&db, user, challenge, &artifact.flag)
+        .await
+        .expect("solve challenge"));
+    assert!(get_solved(&db, user, challenge).await.is_ok());
+}
+
+#[rocket::async_test]
+async fn stopped_instance_is_cleaned_up() {
+    let _lock = LOCK.lock().await;
+    let pool = setup().await;
+    let db = Db::from_pool(&pool).await.expect("database connection");
+
+    let (idx, instance) = start_instance(&db).await;
+
+    stop_docker(idx.0, idx.1, idx.2).await;
+
+    assert!(!is_docker_running(idx.0, idx.1, idx.2).await);
+    assert_cleaned(&db, &instance).await;
+}
+
+#[rocket::async_test]
+async fn evicted_instance_is_cleaned_up() {
+    let _lock = LOCK.lock().await;
+    let pool = setup().await;
+    let db = Db::from_pool(&pool).await.expect("database connection");
+
+    let (idx, instance) = start_instance(&db).await;
+
+    reap_idle_dockers(Duration::ZERO).await;
+
+    assert!(!is_docker_running(idx.0, idx.1, idx.2).await);
+    assert!(is_docker_reaped(idx.0.unwrap(), idx.1, idx.2).await);
+    assert_cleaned(&db, &instance).await;
+}
@@ -3,6 +3,7 @@ extern crate rocket;
 
 #[cfg(feature = "activity")]
 mod activity;
+mod cli;
 mod configs;
 mod core;
 mod db;
@@ -10,13 +11,34 @@ mod functions;
 mod pages;
 mod utils;
 
-use rocket::fs::{FileServer, Options as FsOptions};
+use std::process::ExitCode;
 
-#[launch]
-fn rocket() -> _ {
+use rocket::{
+    fs::{FileServer, Options as FsOptions},
+    Build, Rocket,
+};
+
+fn rocket() -> Rocket<Build> {
     rocket::build()
         .attach(db::stage())
         .attach(functions::stage())
         .attach(pages::stage())
         .mount("/static", FileServer::new("static", FsOptions::None))
 }
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        return cli::run(rocket(), &args).await;
+    }
+
+    match rocket().launch().await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -17,8 +17,8 @@ use crate::{
         },
         Db,
     },
-    functions::challenge::{build_challenge, clear_artifact},
-    pages::{auth_session, OptionFlashExt, Result, ResultFlashExt},
+    functions::challenge::{clear_artifact, enqueue_build, get_build_state},
+    pages::{auth_session, Error, Result, ResultFlashExt},
 };
 
 use super::{check_permission, ResultResponseExt};
@@ -87,9 +87,13 @@ async fn detail(
         .await
         .resp_expect("获取题目失败")?;
 
+    let build = get_build_state(&db, artifact.user, artifact.challenge)
+        .await
+        .resp_expect("获取构建任务失败")?;
+
     Ok(Template::render(
         "admin/artifact/detail",
-        context! { flash, artifact, user, challenge },
+        context! { flash, artifact, user, challenge, build },
     ))
 }
 
@@ -102,13 +106,20 @@ async fn rebuild(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<Flash<Redirect>
         .await
         .flash_expect(uri!(ROOT, index), "获取产物失败")?;
 
-    build_challenge(&db, artifact.user, artifact.challenge)
+    if artifact.pooled {
+        return Err(Error::redirect(
+            uri!(ROOT, detail(id)),
+            "禁止重新构建预构建产物",
+        ));
+    }
+
+    enqueue_build(&db, artifact.user, artifact.challenge)
         .await
-        .flash_expect(uri!(ROOT, index), "构建产物失败")?;
+        .flash_expect(uri!(ROOT, detail(id)), "添加构建任务失败")?;
 
     Ok(Flash::success(
-        Redirect::to(uri!(ROOT, index)),
-        "重新构建产物成功",
+        Redirect::to(uri!(ROOT, detail(id))),
+        "已添加重新构建任务",
     ))
 }
 
@@ -121,9 +132,9 @@ async fn delete(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<Flash<Redirect>>
         .await
         .flash_expect(uri!(ROOT, index), "获取产物失败")?;
 
-    _ = artifact
-        .user
-        .flash_expect(uri!(ROOT, detail(id)), "禁止删除静态产物")?;
+    if artifact.user.is_none() && !artifact.pooled {
+        return Err(Error::redirect(uri!(ROOT, detail(id)), "禁止删除静态产物"));
+    }
 
     clear_artifact(&artifact).await;
 
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use rocket::{
+    fairing::AdHoc,
+    http::{uri::Origin, CookieJar},
+    request::FlashMessage,
+    response::{Flash, Redirect},
+};
+use rocket_dyn_templates::{context, Template};
+use tokio::fs::File;
+
+use crate::{
+    db::{
+        query::{
+            build::{get_build, list_builds},
+            challenge::{get_challenge, list_challenges},
+            user::{get_user, list_users},
+        },
+        Db,
+    },
+    functions::challenge::{open_build_log, read_build_log, remove_build, retry_build},
+    pages::{auth_session, Result, ResultFlashExt},
+    utils::responder::NamedFile,
+};
+
+use super::{check_permission, ResultResponseExt};
+
+#[allow(clippy::declare_interior_mutable_const)]
+pub const ROOT: Origin<'static> = uri!("/admin/build");
+
+#[get("/")]
+async fn index(jar: &CookieJar<'_>, db: Db, flash: Option<FlashMessage<'_>>) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let users: HashMap<_, _> = list_users(&db)
+        .await
+        .resp_expect("获取用户列表失败")?
+        .into_iter()
+        .map(|user| (user.id.unwrap(), user))
+        .collect();
+
+    let challenges: HashMap<_, _> = list_challenges(&db)
+        .await
+        .resp_expect("获取题目列表失败")?
+        .into_iter()
+        .map(|challenge| (challenge.id.unwrap(), challenge))
+        .collect();
+
+    let builds: Vec<_> = list_builds(&db)
+        .await
+        .resp_expect("获取构建任务列表失败")?
+        .into_iter()
+        .map(|build| {
+            context! {
+                user: build.user.map(|user| users.get(&user)),
+                challenge: challenges.get(&build.challenge).expect("foreign key"),
+                build,
+            }
+        })
+        .collect();
+
+    Ok(Template::render(
+        "admin/build/index",
+        context! { flash, builds },
+    ))
+}
+
+#[get("/<id>")]
+async fn detail(
+    jar: &CookieJar<'_>,
+    db: Db,
+    flash: Option<FlashMessage<'_>>,
+    id: i32,
+) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let build = get_build(&db, id).await.resp_expect("获取构建任务失败")?;
+
+    let user = match build.user {
+        Some(user) => Some(get_user(&db, user).await.resp_expect("获取用户失败")?),
+        None => None,
+    };
+
+    let challenge = get_challenge(&db, build.challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+
+    Ok(Template::render(
+        "admin/build/detail",
+        context! { flash, build, user, challenge },
+    ))
+}
+
+#[get("/<id>/log")]
+async fn log(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<String> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let build = get_build(&db, id).await.resp_expect("获取构建任务失败")?;
+
+    let log = read_build_log(&build)
+        .await
+        .resp_expect("获取构建日志失败")?;
+
+    Ok(log)
+}
+
+#[get("/<id>/log/download")]
+async fn download_log(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<NamedFile<File>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let build = get_build(&db, id)
+        .await
+        .flash_expect(uri!(ROOT, index), "获取构建任务失败")?;
+
+    let file = open_build_log(&build)
+        .await
+        .flash_expect(uri!(ROOT, detail(id)), "获取构建日志失败")?;
+
+    Ok(file)
+}
+
+#[get("/<id>/retry")]
+async fn retry(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    retry_build(&db, id)
+        .await
+        .flash_expect(uri!(ROOT, detail(id)), "重试构建任务失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, index)),
+        "已重新添加构建任务",
+    ))
+}
+
+#[delete("/<id>")]
+async fn delete(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    remove_build(&db, id)
+        .await
+        .flash_expect(uri!(ROOT, detail(id)), "删除构建任务失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, index)),
+        "删除构建任务成功",
+    ))
+}
+
+pub fn stage() -> AdHoc {
+    let routes = routes![index, detail, log, download_log, retry, delete];
+
+    AdHoc::on_ignite("Admin Pages - Build", |rocket| async {
+        rocket.mount(ROOT, routes)
+    })
+}
@@ -11,9 +11,11 @@ use rocket::{
 use rocket_dyn_templates::{context, Template};
 
 use crate::{
+    core::conductor::Artifact,
     db::{
         models::Challenge,
         query::{
+            artifact::get_artifact,
             challenge::{
                 add_challenge, delete_challenge, get_challenge, list_challenges,
                 list_private_challenges, publish_challenge, update_challenge,
@@ -24,8 +26,9 @@ use crate::{
         Db,
     },
     functions::challenge::{
-        build_challenge, load_build_info, recalculate_challenge_points, recalculate_points,
-        remove_challenge, save_files,
+        build_challenge, import_challenge_package, is_docker_running, load_build_info,
+        recalculate_challenge_points, recalculate_points, refill_pool, remove_challenge,
+        save_files, stop_active_sessions, sync_challenges,
     },
     pages::{auth_session, Error, Result, ResultFlashExt},
 };
@@ -44,6 +47,10 @@ struct Edit<'r> {
     pub points: Option<f64>,
     pub public: bool,
     pub difficulty: Option<i32>,
+    #[field(validate = with(|x| x.map(|v| v >= 0).unwrap_or(true), "pool size too low."))]
+    pub pool: Option<i32>,
+    pub hardened: bool,
+    pub shared: bool,
 }
 
 #[derive(Debug, FromForm)]
@@ -60,6 +67,15 @@ struct New<'r> {
     pub flag: &'r str,
     pub public: bool,
     pub difficulty: Option<i32>,
+    #[field(validate = with(|x| x.map(|v| v >= 0).unwrap_or(true), "pool size too low."))]
+    pub pool: Option<i32>,
+    pub hardened: bool,
+    pub shared: bool,
+}
+
+#[derive(Debug, FromForm)]
+struct Import<'r> {
+    pub package: TempFile<'r>,
 }
 
 #[derive(Debug, FromForm)]
@@ -199,6 +215,13 @@ async fn new(jar: &CookieJar<'_>, db: Db, mut info: Form<New<'_>>) -> Result<Fla
         return Err(Error::redirect(uri!(ROOT, new_page), "未上传源代码"));
     }
 
+    if info.dynamic && info.shared {
+        return Err(Error::redirect(
+            uri!(ROOT, new_page),
+            "动态题目不能使用共享实例",
+        ));
+    }
+
     if info.flag.is_empty() {
         if info.dynamic {
             info.flag = "flag{{{}}}";
@@ -226,6 +249,10 @@ async fn new(jar: &CookieJar<'_>, db: Db, mut info: Form<New<'_>>) -> Result<Fla
         points: info.points,
         public: info.public,
         difficulty: info.difficulty,
+        pool: info.pool,
+        hardened: info.hardened,
+        shared: info.shared,
+        slug: None,
     };
 
     let challenge = add_challenge(&db, challenge)
@@ -242,12 +269,87 @@ async fn new(jar: &CookieJar<'_>, db: Db, mut info: Form<New<'_>>) -> Result<Fla
         result.flash_expect(uri!(ROOT, new_page), "构建题目失败")?;
     }
 
+    if info.dynamic {
+        refill_pool(&db, challenge)
+            .await
+            .flash_expect(uri!(ROOT, index), "添加预构建任务失败")?;
+    }
+
     Ok(Flash::success(
         Redirect::to(uri!(ROOT, index)),
         "添加题目成功",
     ))
 }
 
+#[get("/import")]
+async fn import_page(
+    jar: &CookieJar<'_>,
+    db: Db,
+    flash: Option<FlashMessage<'_>>,
+) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    Ok(Template::render("admin/challenge/import", context! {flash}))
+}
+
+#[post("/import", data = "<info>")]
+async fn import(jar: &CookieJar<'_>, db: Db, info: Form<Import<'_>>) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let imported = import_challenge_package(&db, info.into_inner().package)
+        .await
+        .flash_expect(uri!(ROOT, import_page), "导入题目失败")?;
+
+    if !imported.dynamic && imported.has_source {
+        let result = build_challenge(&db, None, imported.id).await;
+
+        if result.is_err() && imported.created {
+            _ = remove_challenge(&db, imported.id).await;
+        }
+
+        result.flash_expect(uri!(ROOT, import_page), "构建题目失败")?;
+    }
+
+    if imported.dynamic {
+        refill_pool(&db, imported.id)
+            .await
+            .flash_expect(uri!(ROOT, index), "添加预构建任务失败")?;
+    }
+
+    let message = match imported.created {
+        true => "导入题目成功",
+        false => "更新题目成功",
+    };
+
+    Ok(Flash::success(Redirect::to(uri!(ROOT, index)), message))
+}
+
+#[get("/sync")]
+async fn sync_page(jar: &CookieJar<'_>, db: Db) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let report = sync_challenges(&db, true)
+        .await
+        .resp_expect("获取同步预览失败")?;
+
+    Ok(Template::render("admin/challenge/sync", context! {report}))
+}
+
+#[post("/sync")]
+async fn sync(jar: &CookieJar<'_>, db: Db) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let report = sync_challenges(&db, false)
+        .await
+        .resp_expect("同步题目失败")?;
+
+    Ok(Template::render("admin/challenge/sync", context! {report}))
+}
+
 #[get("/<id>")]
 async fn edit_page(
     jar: &CookieJar<'_>,
@@ -288,8 +390,16 @@ async fn edit(
         .await
         .flash_expect(uri!(ROOT, index), "获取题目失败")?;
 
+    if challenge.dynamic && info.shared {
+        return Err(Error::redirect(
+            uri!(ROOT, edit_page(id)),
+            "动态题目不能使用共享实例",
+        ));
+    }
+
     let points = info.points.unwrap_or(challenge.initial);
     let recalculate = points != challenge.initial;
+    let switched = info.shared != challenge.shared;
 
     let new_challenge = Challenge {
         id: Some(id),
@@ -307,12 +417,24 @@ async fn edit(
         points: challenge.points,
         public: info.public,
         difficulty: info.difficulty,
+        pool: info.pool,
+        hardened: info.hardened,
+        shared: info.shared,
+        slug: challenge.slug,
     };
 
     update_challenge(&db, new_challenge)
         .await
         .flash_expect(uri!(ROOT, edit_page(id)), "修改题目信息失败")?;
 
+    refill_pool(&db, id)
+        .await
+        .flash_expect(uri!(ROOT, edit_page(id)), "添加预构建任务失败")?;
+
+    if switched {
+        stop_active_sessions(None, id).await;
+    }
+
     if recalculate {
         recalculate_challenge_points(&db, id)
             .await
@@ -353,9 +475,22 @@ async fn detail(
     let challenge = get_challenge(&db, id).await.resp_expect("获取题目失败")?;
     let build = load_build_info(&db, id).await.ok();
 
+    let mut instances = Vec::new();
+
+    if challenge.shared {
+        if let Ok(artifact) = get_artifact(&db, id, None).await {
+            for (idx, info) in artifact.info.iter().enumerate() {
+                if matches!(info, Artifact::Docker(_) | Artifact::Compose(_)) {
+                    let running = is_docker_running(None, id, idx).await;
+                    instances.push(context! { artifact: idx, running });
+                }
+            }
+        }
+    }
+
     Ok(Template::render(
         "admin/challenge/detail",
-        context! {flash, challenge, build},
+        context! {flash, challenge, build, instances},
     ))
 }
 
@@ -381,6 +516,10 @@ pub fn stage() -> AdHoc {
         publish,
         new_page,
         new,
+        import_page,
+        import,
+        sync_page,
+        sync,
         edit_page,
         edit,
         delete,
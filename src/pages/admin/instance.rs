@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use futures_util::future::join_all;
+use rocket::{
+    fairing::AdHoc,
+    http::{uri::Origin, CookieJar},
+    request::FlashMessage,
+    response::{Flash, Redirect},
+};
+use rocket_dyn_templates::{context, Template};
+
+use crate::{
+    db::{
+        query::{
+            challenge::{get_challenge, list_challenges},
+            user::{get_user, list_users},
+        },
+        Db,
+    },
+    functions::challenge::{
+        extend_docker, get_docker_instance_info, get_docker_instance_stats, list_docker_instances,
+        read_docker_instance_logs, restart_docker, run_docker, stop_docker,
+    },
+    pages::{auth_session, Result, ResultFlashExt},
+};
+
+use super::{check_permission, ResultResponseExt};
+
+#[allow(clippy::declare_interior_mutable_const)]
+pub const ROOT: Origin<'static> = uri!("/admin/instance");
+
+#[get("/")]
+async fn index(jar: &CookieJar<'_>, db: Db, flash: Option<FlashMessage<'_>>) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let users: HashMap<_, _> = list_users(&db)
+        .await
+        .resp_expect("获取用户列表失败")?
+        .into_iter()
+        .map(|user| (user.id.unwrap(), user))
+        .collect();
+
+    let challenges: HashMap<_, _> = list_challenges(&db)
+        .await
+        .resp_expect("获取题目列表失败")?
+        .into_iter()
+        .map(|challenge| (challenge.id.unwrap(), challenge))
+        .collect();
+
+    let instances = list_docker_instances().await;
+
+    let stats = join_all(instances.iter().map(|instance| {
+        get_docker_instance_stats(instance.user, instance.challenge, instance.artifact)
+    }))
+    .await;
+
+    let instances: Vec<_> = instances
+        .into_iter()
+        .zip(stats)
+        .map(|(instance, stats)| {
+            context! {
+                user: instance.user.and_then(|user| users.get(&user)),
+                challenge: challenges.get(&instance.challenge),
+                artifact: instance.artifact,
+                user_id: instance.user,
+                challenge_id: instance.challenge,
+                ports: instance.info.ports,
+                age: instance.age.as_secs(),
+                expiry: instance.expiry.map(|x| x.as_secs()),
+                stats: stats.ok(),
+            }
+        })
+        .collect();
+
+    Ok(Template::render(
+        "admin/instance/index",
+        context! { flash, instances },
+    ))
+}
+
+#[get("/<challenge>/<artifact>?<user>&<tail>")]
+async fn detail(
+    jar: &CookieJar<'_>,
+    db: Db,
+    flash: Option<FlashMessage<'_>>,
+    challenge: i32,
+    artifact: usize,
+    user: Option<i32>,
+    tail: Option<usize>,
+) -> Result<Template> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    let tail = tail.unwrap_or(200);
+
+    let info = get_docker_instance_info(user, challenge, artifact)
+        .await
+        .resp_expect("获取实例信息失败")?;
+
+    let stats = get_docker_instance_stats(user, challenge, artifact)
+        .await
+        .ok();
+
+    let logs = read_docker_instance_logs(user, challenge, artifact, tail)
+        .await
+        .resp_expect("获取实例日志失败")?;
+
+    let user_id = user;
+    let challenge_id = challenge;
+
+    let user = match user {
+        Some(user) => Some(get_user(&db, user).await.resp_expect("获取用户失败")?),
+        None => None,
+    };
+    let challenge = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+
+    let instance = context! {
+        user_id,
+        challenge_id,
+        artifact,
+        expiry: info.expiry.map(|x| x.as_secs()),
+        ports: info.ports,
+        urls: info.urls,
+        connections: info.connections,
+    };
+
+    Ok(Template::render(
+        "admin/instance/detail",
+        context! { flash, user, challenge, instance, stats, logs, tail },
+    ))
+}
+
+#[get("/<challenge>/<artifact>/extend?<user>")]
+async fn extend(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+    user: Option<i32>,
+) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    extend_docker(&db, user, challenge, artifact, true)
+        .await
+        .flash_expect(
+            uri!(ROOT, detail(challenge, artifact, user, _)),
+            "延长实例失败",
+        )?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge, artifact, user, _))),
+        "延长实例成功",
+    ))
+}
+
+#[get("/<challenge>/<artifact>/restart?<user>")]
+async fn restart(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+    user: Option<i32>,
+) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    restart_docker(user, challenge, artifact, true)
+        .await
+        .flash_expect(
+            uri!(ROOT, detail(challenge, artifact, user, _)),
+            "重启实例失败",
+        )?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge, artifact, user, _))),
+        "重启实例成功",
+    ))
+}
+
+#[get("/<challenge>/<artifact>/run")]
+async fn run(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    run_docker(&db, None, challenge, artifact)
+        .await
+        .flash_expect(uri!(ROOT, index), "启动共享实例失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge, artifact, None::<i32>, _))),
+        "启动共享实例成功",
+    ))
+}
+
+#[delete("/<challenge>/<artifact>?<user>")]
+async fn delete(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+    user: Option<i32>,
+) -> Result<Flash<Redirect>> {
+    let current = auth_session(&db, jar).await?;
+    check_permission(&current)?;
+
+    stop_docker(user, challenge, artifact).await;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, index)),
+        "停止实例成功",
+    ))
+}
+
+pub fn stage() -> AdHoc {
+    let routes = routes![index, detail, extend, restart, run, delete];
+
+    AdHoc::on_ignite("Admin Pages - Instance", |rocket| async {
+        rocket.mount(ROOT, routes)
+    })
+}
@@ -1,6 +1,8 @@
 pub mod artifact;
+pub mod build;
 pub mod challenge;
 pub mod difficulty;
+pub mod instance;
 pub mod problemset;
 pub mod root;
 pub mod submission;
@@ -65,8 +67,10 @@ pub fn stage() -> AdHoc {
     AdHoc::on_ignite("Admin Pages", |rocket| async {
         rocket
             .attach(artifact::stage())
+            .attach(build::stage())
             .attach(challenge::stage())
             .attach(difficulty::stage())
+            .attach(instance::stage())
             .attach(problemset::stage())
             .attach(root::stage())
             .attach(submission::stage())
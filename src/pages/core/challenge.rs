@@ -4,6 +4,7 @@ use itertools::Itertools;
 use rocket::{
     fairing::AdHoc,
     form::Form,
+    fs::TempFile,
     http::{uri::Origin, CookieJar},
     request::FlashMessage,
     response::{Flash, Redirect},
@@ -12,9 +13,9 @@ use rocket_dyn_templates::{context, Template};
 use tokio::fs::File;
 
 use crate::{
-    core::conductor::Artifact,
+    core::conductor::{self, Artifact},
     db::{
-        models::{Challenge, User},
+        models::{BuildStatus, Challenge, User},
         query::{
             artifact::get_artifact,
             challenge::{get_challenge, list_challenges},
@@ -29,14 +30,20 @@ use crate::{
     },
     functions::{
         challenge::{
-            build_challenge, get_docker_instance_info, is_challenge_building, is_docker_running,
-            is_publicly_available, open_attachment, open_binary, open_docker_states, run_docker,
-            solve_challenge, stop_docker,
+            bridge_docker_terminal, enqueue_build, extend_docker, get_build_state,
+            get_docker_instance_info, get_docker_instance_usage, is_docker_reaped,
+            is_docker_running, is_publicly_available, open_attachment, open_binary,
+            open_docker_states, open_docker_terminal, reset_docker, restart_docker,
+            restore_docker_states, run_docker, solve_challenge, stop_docker,
         },
         user::is_admin,
     },
     pages::{auth_session, Error, Result, ResultFlashExt},
-    utils::{query::QueryResultExt, responder::NamedFile},
+    utils::{
+        query::QueryResultExt,
+        responder::NamedFile,
+        websocket::{Channel, WebSocket},
+    },
 };
 
 use super::{check_event_availability, ResultResponseExt};
@@ -50,6 +57,11 @@ struct Solve<'r> {
     pub flag: &'r str,
 }
 
+#[derive(Debug, FromForm)]
+struct States<'r> {
+    pub archive: TempFile<'r>,
+}
+
 #[allow(clippy::result_large_err)]
 #[inline]
 fn check_challenge_availability(user: &User, challenge: &Challenge) -> Result<()> {
@@ -171,7 +183,13 @@ async fn detail(
         .some()
         .resp_expect("获取解题状态失败")?;
 
-    let building = is_challenge_building(challenge.dynamic.then_some(user_id), id).await;
+    let build = get_build_state(&db, challenge.dynamic.then_some(user_id), id)
+        .await
+        .resp_expect("获取构建状态失败")?;
+
+    let building = build
+        .as_ref()
+        .is_some_and(|state| state.build.status.is_pending());
 
     let artifact = match building {
         false => get_artifact(&db, id, challenge.dynamic.then_some(user_id))
@@ -181,31 +199,54 @@ async fn detail(
         true => None,
     };
 
-    let mut dockers = HashMap::new();
+    let mut dockers = Vec::new();
+    let mut reaped = Vec::new();
+    let mut usage = None;
+
+    // the shared instance of a challenge is shown to every player.
+    let owner = (!challenge.shared).then_some(user_id);
 
     if let Some(artifact) = &artifact {
+        if !challenge.shared
+            && artifact
+                .info
+                .iter()
+                .any(|artifact| matches!(artifact, Artifact::Docker(_) | Artifact::Compose(_)))
+        {
+            usage = Some(get_docker_instance_usage(user_id).await);
+        }
+
+        dockers.resize_with(artifact.info.len(), || None);
+        reaped.resize(artifact.info.len(), false);
+
         for (idx, artifact) in artifact.info.iter().enumerate() {
-            if let Artifact::Docker(docker) = artifact {
-                if is_docker_running(user_id, id, idx).await {
-                    let info = get_docker_instance_info(user_id, id, idx)
-                        .await
-                        .resp_expect("获取 Docker 实例信息失败")?;
-
-                    dockers.insert(
-                        docker.id.clone(),
-                        context! {
-                            expiry: info.expiry.map(|x| x.as_secs()),
-                            ports: info.ports,
-                        },
-                    );
-                }
+            if !matches!(artifact, Artifact::Docker(_) | Artifact::Compose(_)) {
+                continue;
             }
+
+            if !is_docker_running(owner, id, idx).await {
+                reaped[idx] = !challenge.shared && is_docker_reaped(user_id, id, idx).await;
+                continue;
+            }
+
+            let info = get_docker_instance_info(owner, id, idx)
+                .await
+                .resp_expect("获取 Docker 实例信息失败")?;
+
+            dockers[idx] = Some(context! {
+                expiry: info.expiry.map(|x| x.as_secs()),
+                extendable: info.extendable,
+                ports: info.ports,
+                urls: info.urls,
+                connections: info.connections,
+                terminal: conductor::terminal_config(artifact).is_some(),
+            });
         }
     }
 
     Ok(Template::render(
         "core/challenge/detail",
-        context! {flash, challenge, problemset, difficulty, solved, solved_count, artifact, dockers, building},
+        context! {flash, challenge, problemset, difficulty, solved, solved_count, artifact, dockers, reaped, usage, build, building},
     ))
 }
 
@@ -219,14 +260,16 @@ async fn build(jar: &CookieJar<'_>, db: Db, id: i32) -> Result<Flash<Redirect>>
 
     let user_id = user.id.unwrap();
 
-    build_challenge(&db, Some(user_id), id)
+    let build = enqueue_build(&db, Some(user_id), id)
         .await
-        .flash_expect(uri!(ROOT, detail(id)), "构建题目失败")?;
+        .flash_expect(uri!(ROOT, detail(id)), "添加构建任务失败")?;
 
-    Ok(Flash::success(
-        Redirect::to(uri!(ROOT, detail(id))),
-        "构建成功",
-    ))
+    let msg = match build.status {
+        BuildStatus::Succeeded => "构建成功",
+        _ => "已加入构建队列",
+    };
+
+    Ok(Flash::success(Redirect::to(uri!(ROOT, detail(id))), msg))
 }
 
 #[get("/<challenge>/artifact/binary/<artifact>")]
@@ -288,7 +331,7 @@ async fn artifact_docker_run(
         .resp_expect("获取题目失败")?;
     check_challenge_availability(&user, &entry)?;
 
-    run_docker(&db, user.id.unwrap(), challenge, artifact)
+    run_docker(&db, user.id, challenge, artifact)
         .await
         .flash_expect(uri!(ROOT, detail(challenge)), "启动容器失败")?;
 
@@ -298,6 +341,31 @@ async fn artifact_docker_run(
     ))
 }
 
+#[get("/<challenge>/artifact/docker/<artifact>/extend")]
+async fn artifact_docker_extend(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Flash<Redirect>> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    extend_docker(&db, user.id, challenge, artifact, false)
+        .await
+        .flash_expect(uri!(ROOT, detail(challenge)), "延长容器时间失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge))),
+        "延长容器时间成功",
+    ))
+}
+
 #[get("/<challenge>/artifact/docker/<artifact>/stop")]
 async fn artifact_docker_stop(
     jar: &CookieJar<'_>,
@@ -313,7 +381,7 @@ async fn artifact_docker_stop(
         .resp_expect("获取题目失败")?;
     check_challenge_availability(&user, &entry)?;
 
-    stop_docker(user.id.unwrap(), challenge, artifact).await;
+    stop_docker(user.id, challenge, artifact).await;
 
     Ok(Flash::success(
         Redirect::to(uri!(ROOT, detail(challenge))),
@@ -321,6 +389,56 @@ async fn artifact_docker_stop(
     ))
 }
 
+#[get("/<challenge>/artifact/docker/<artifact>/restart")]
+async fn artifact_docker_restart(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Flash<Redirect>> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    restart_docker(user.id, challenge, artifact, false)
+        .await
+        .flash_expect(uri!(ROOT, detail(challenge)), "重启容器失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge))),
+        "重启容器成功",
+    ))
+}
+
+#[get("/<challenge>/artifact/docker/<artifact>/reset")]
+async fn artifact_docker_reset(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Flash<Redirect>> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    reset_docker(&db, user.id, challenge, artifact, false)
+        .await
+        .flash_expect(uri!(ROOT, detail(challenge)), "重置容器失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge))),
+        "重置容器成功",
+    ))
+}
+
 #[get("/<challenge>/artifact/docker/<artifact>/states")]
 async fn artifact_docker_states(
     jar: &CookieJar<'_>,
@@ -336,13 +454,106 @@ async fn artifact_docker_states(
         .resp_expect("获取题目失败")?;
     check_challenge_availability(&user, &entry)?;
 
-    let file = open_docker_states(user.id.unwrap(), challenge, artifact)
+    let file = open_docker_states(user.id, challenge, artifact)
         .await
         .flash_expect(uri!(ROOT, detail(challenge)), "获取构建产物失败")?;
 
     Ok(file)
 }
 
+#[post("/<challenge>/artifact/docker/<artifact>/states", data = "<states>")]
+async fn artifact_docker_states_upload(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+    states: Form<States<'_>>,
+) -> Result<Flash<Redirect>> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    restore_docker_states(
+        &db,
+        user.id,
+        challenge,
+        artifact,
+        states.into_inner().archive,
+    )
+    .await
+    .flash_expect(uri!(ROOT, detail(challenge)), "上传状态文件失败")?;
+
+    Ok(Flash::success(
+        Redirect::to(uri!(ROOT, detail(challenge))),
+        "上传状态文件成功",
+    ))
+}
+
+#[get("/<challenge>/artifact/docker/<artifact>/terminal")]
+async fn artifact_docker_terminal(
+    jar: &CookieJar<'_>,
+    db: Db,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Template> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    let user_id = user.id.unwrap();
+
+    if !is_docker_running(Some(user_id), challenge, artifact).await {
+        return Err(Error::redirect(uri!(ROOT, detail(challenge)), "容器未启动"));
+    }
+
+    let info = get_artifact(&db, challenge, entry.dynamic.then_some(user_id))
+        .await
+        .flash_expect(uri!(ROOT, detail(challenge)), "获取构建产物信息失败")?;
+
+    let Some(terminal) = info.info.get(artifact).and_then(conductor::terminal_config) else {
+        return Err(Error::redirect(
+            uri!(ROOT, detail(challenge)),
+            "该产物未启用网页终端",
+        ));
+    };
+
+    Ok(Template::render(
+        "core/challenge/terminal",
+        context! {challenge: entry, artifact, terminal},
+    ))
+}
+
+#[get("/<challenge>/artifact/docker/<artifact>/terminal/ws")]
+async fn artifact_docker_terminal_ws(
+    jar: &CookieJar<'_>,
+    db: Db,
+    ws: WebSocket,
+    challenge: i32,
+    artifact: usize,
+) -> Result<Channel> {
+    let user = auth_session(&db, jar).await?;
+    check_event_availability(Some(&user))?;
+
+    let entry = get_challenge(&db, challenge)
+        .await
+        .resp_expect("获取题目失败")?;
+    check_challenge_availability(&user, &entry)?;
+
+    let terminal = open_docker_terminal(&db, user.id.unwrap(), challenge, artifact)
+        .await
+        .resp_expect("打开网页终端失败")?;
+
+    Ok(ws.channel(move |socket| bridge_docker_terminal(socket, terminal)))
+}
+
 #[post("/<id>/solve", data = "<solve>")]
 async fn solve(
     jar: &CookieJar<'_>,
@@ -394,8 +605,14 @@ pub fn stage() -> AdHoc {
         attachment,
         artifact_binary,
         artifact_docker_run,
+        artifact_docker_extend,
         artifact_docker_stop,
+        artifact_docker_restart,
+        artifact_docker_reset,
         artifact_docker_states,
+        artifact_docker_states_upload,
+        artifact_docker_terminal,
+        artifact_docker_terminal_ws,
     ];
 
     AdHoc::on_ignite("Core Pages - Challenge", |rocket| async {
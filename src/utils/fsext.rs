@@ -26,7 +26,7 @@ impl WalkDir {
         })
     }
 
-    pub fn next_entry(&mut self) -> BoxFuture<io::Result<Option<DirEntry>>> {
+    pub fn next_entry(&mut self) -> BoxFuture<'_, io::Result<Option<DirEntry>>> {
         async move {
             if let Some(inner) = self.inner.as_mut() {
                 if let Some(entry) = inner.next_entry().await? {
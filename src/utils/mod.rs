@@ -5,3 +5,4 @@ pub mod query;
 pub mod responder;
 pub mod script;
 pub mod webcolor;
+pub mod websocket;
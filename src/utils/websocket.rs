@@ -0,0 +1,82 @@
+use std::{future::Future, pin::Pin};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use rocket::{
+    data::{IoHandler, IoStream},
+    http::Status,
+    request::{FromRequest, Outcome},
+    response::{Responder, Result as ResponseResult},
+    Request, Response,
+};
+use tokio::io::Result as IoResult;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role},
+    WebSocketStream,
+};
+
+pub type WebSocketConnection = WebSocketStream<IoStream>;
+
+type Handler = Box<dyn FnOnce(WebSocketConnection) -> BoxFuture<'static, ()> + Send>;
+
+pub struct WebSocket {
+    accept: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocket {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+
+        let upgrade = headers
+            .get("Upgrade")
+            .any(|x| x.eq_ignore_ascii_case("websocket"));
+
+        match headers.get_one("Sec-WebSocket-Key") {
+            Some(key) if upgrade => Outcome::Success(Self {
+                accept: derive_accept_key(key.as_bytes()),
+            }),
+            _ => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+impl WebSocket {
+    pub fn channel<F, Fut>(self, handler: F) -> Channel
+    where
+        F: FnOnce(WebSocketConnection) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Channel {
+            accept: self.accept,
+            handler: Box::new(move |socket| handler(socket).boxed()),
+        }
+    }
+}
+
+pub struct Channel {
+    accept: String,
+    handler: Handler,
+}
+
+impl<'r> Responder<'r, 'static> for Channel {
+    fn respond_to(self, _: &'r Request<'_>) -> ResponseResult<'static> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", self.accept.clone())
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Channel {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> IoResult<()> {
+        let channel = Pin::into_inner(self);
+        let socket = WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+
+        (channel.handler)(socket).await;
+
+        Ok(())
+    }
+}
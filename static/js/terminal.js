@@ -0,0 +1,64 @@
+import { Terminal } from 'https://cdn.jsdelivr.net/npm/@xterm/xterm@5.5.0/+esm'
+import { FitAddon } from 'https://cdn.jsdelivr.net/npm/@xterm/addon-fit@0.10.0/+esm'
+
+// raw tcp services expect lines, so echo and edit them locally like a cooked tty.
+const lineDiscipline = (terminal, send) => {
+  let line = '';
+
+  return data => {
+    for (const char of data) {
+      if (char === '\r') {
+        terminal.write('\r\n');
+        send(line + '\n');
+        line = '';
+      } else if (char === '\x7f') {
+        if (line.length > 0) {
+          line = line.slice(0, -1);
+          terminal.write('\b \b');
+        }
+      } else if (char >= ' ') {
+        line += char;
+        terminal.write(char);
+      }
+    }
+  };
+};
+
+export const openTerminal = (element, path, lineMode) => {
+  const terminal = new Terminal({ cursorBlink: true, convertEol: lineMode });
+  const fit = new FitAddon();
+
+  terminal.loadAddon(fit);
+  terminal.open(element);
+  fit.fit();
+
+  const protocol = location.protocol === 'https:' ? 'wss:' : 'ws:';
+  const socket = new WebSocket(`${protocol}//${location.host}${path}`);
+  socket.binaryType = 'arraybuffer';
+
+  const encoder = new TextEncoder();
+
+  const send = data => {
+    if (socket.readyState === WebSocket.OPEN) {
+      socket.send(encoder.encode(data));
+    }
+  };
+
+  const resize = () => {
+    if (socket.readyState === WebSocket.OPEN) {
+      socket.send(JSON.stringify({ type: 'resize', rows: terminal.rows, cols: terminal.cols }));
+    }
+  };
+
+  socket.addEventListener('open', () => {
+    resize();
+    terminal.focus();
+  });
+  socket.addEventListener('message', event => terminal.write(new Uint8Array(event.data)));
+  socket.addEventListener('close', () => terminal.write('\r\n\x1b[33m[连接已关闭]\x1b[0m\r\n'));
+
+  terminal.onData(lineMode ? lineDiscipline(terminal, send) : send);
+  terminal.onResize(resize);
+
+  window.addEventListener('resize', () => fit.fit());
+};